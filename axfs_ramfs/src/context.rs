@@ -0,0 +1,289 @@
+use alloc::sync::Arc;
+use axfs_vfs::{VfsError, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsResult};
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use spin::RwLock;
+
+/// The kind of access checked by an access checker installed with
+/// [`RamFileSystem::set_access_checker`](crate::RamFileSystem::set_access_checker).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessMode {
+    /// Reading the content of a file or listing a directory.
+    Read,
+    /// Modifying the content of a file.
+    Write,
+    /// Looking up an entry in a directory.
+    Lookup,
+}
+
+/// Usage statistics of a [`RamFileSystem`](crate::RamFileSystem), as returned
+/// by [`RamFileSystem::stats`](crate::RamFileSystem::stats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RamFsStats {
+    /// Total bytes of file content.
+    pub total_bytes: usize,
+    /// Number of regular files.
+    pub files: usize,
+    /// Number of directories, including the root.
+    pub dirs: usize,
+    /// Number of symbolic links.
+    pub symlinks: usize,
+}
+
+/// The default maximum length of a symbolic link target, as `PATH_MAX` in
+/// Linux.
+const DEFAULT_MAX_SYMLINK_LEN: usize = 4096;
+
+pub(crate) type AccessChecker = dyn Fn(&VfsNodeRef, AccessMode) -> bool + Send + Sync;
+pub(crate) type TimeSource = dyn Fn() -> u64 + Send + Sync;
+
+/// Filesystem-wide state shared by all nodes of a
+/// [`RamFileSystem`](crate::RamFileSystem).
+pub(crate) struct FsContext {
+    auto_mkdir: AtomicBool,
+    case_insensitive: AtomicBool,
+    readonly: AtomicBool,
+    nodev: AtomicBool,
+    noexec: AtomicBool,
+    access_checker: RwLock<Option<Arc<AccessChecker>>>,
+    next_ino: AtomicU64,
+    time_source: RwLock<Option<Arc<TimeSource>>>,
+    capacity: AtomicUsize,
+    max_symlink_len: AtomicUsize,
+    max_dir_entries: AtomicUsize,
+    memory_usage: AtomicUsize,
+    peak_memory_usage: AtomicUsize,
+    files: AtomicUsize,
+    dirs: AtomicUsize,
+    symlinks: AtomicUsize,
+}
+
+impl FsContext {
+    pub const fn new() -> Self {
+        Self {
+            auto_mkdir: AtomicBool::new(false),
+            case_insensitive: AtomicBool::new(false),
+            readonly: AtomicBool::new(false),
+            nodev: AtomicBool::new(false),
+            noexec: AtomicBool::new(false),
+            access_checker: RwLock::new(None),
+            next_ino: AtomicU64::new(1),
+            time_source: RwLock::new(None),
+            capacity: AtomicUsize::new(usize::MAX),
+            max_symlink_len: AtomicUsize::new(DEFAULT_MAX_SYMLINK_LEN),
+            max_dir_entries: AtomicUsize::new(usize::MAX),
+            memory_usage: AtomicUsize::new(0),
+            peak_memory_usage: AtomicUsize::new(0),
+            files: AtomicUsize::new(0),
+            dirs: AtomicUsize::new(0),
+            symlinks: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns a context with the same settings, but no nodes accounted.
+    pub fn clone_settings(&self) -> Self {
+        let ctx = Self::new();
+        ctx.set_auto_mkdir(self.auto_mkdir());
+        ctx.set_case_insensitive(self.case_insensitive());
+        ctx.set_readonly(self.readonly());
+        ctx.set_nodev(self.nodev.load(Ordering::Relaxed));
+        ctx.set_noexec(self.noexec.load(Ordering::Relaxed));
+        ctx.set_access_checker(self.access_checker.read().clone());
+        *ctx.time_source.write() = self.time_source.read().clone();
+        ctx.set_capacity(self.capacity());
+        ctx.set_max_symlink_len(self.max_symlink_len.load(Ordering::Relaxed));
+        ctx.set_max_dir_entries(self.max_dir_entries());
+        ctx
+    }
+
+    pub fn auto_mkdir(&self) -> bool {
+        self.auto_mkdir.load(Ordering::Relaxed)
+    }
+
+    pub fn set_auto_mkdir(&self, enabled: bool) {
+        self.auto_mkdir.store(enabled, Ordering::Relaxed)
+    }
+
+    pub fn case_insensitive(&self) -> bool {
+        self.case_insensitive.load(Ordering::Relaxed)
+    }
+
+    pub fn set_case_insensitive(&self, enabled: bool) {
+        self.case_insensitive.store(enabled, Ordering::Relaxed)
+    }
+
+    pub fn readonly(&self) -> bool {
+        self.readonly.load(Ordering::Acquire)
+    }
+
+    pub fn set_readonly(&self, readonly: bool) {
+        self.readonly.store(readonly, Ordering::Release)
+    }
+
+    pub fn set_nodev(&self, enabled: bool) {
+        self.nodev.store(enabled, Ordering::Relaxed)
+    }
+
+    pub fn set_noexec(&self, enabled: bool) {
+        self.noexec.store(enabled, Ordering::Relaxed)
+    }
+
+    /// Fails if nodes of type `ty` are forbidden by the mount options.
+    pub fn check_node_type(&self, ty: VfsNodeType) -> VfsResult {
+        let is_dev = matches!(ty, VfsNodeType::CharDevice | VfsNodeType::BlockDevice);
+        if is_dev && self.nodev.load(Ordering::Relaxed) {
+            return Err(VfsError::PermissionDenied);
+        }
+        Ok(())
+    }
+
+    /// Removes the permission bits of a file forbidden by the mount options.
+    pub fn mask_file_perm(&self, perm: VfsNodePerm) -> VfsNodePerm {
+        if self.noexec.load(Ordering::Relaxed) {
+            perm - (VfsNodePerm::OWNER_EXEC | VfsNodePerm::GROUP_EXEC | VfsNodePerm::OTHER_EXEC)
+        } else {
+            perm
+        }
+    }
+
+    pub fn set_access_checker(&self, checker: Option<Arc<AccessChecker>>) {
+        *self.access_checker.write() = checker;
+    }
+
+    /// Asks the access checker whether `mode` access to a node is allowed.
+    ///
+    /// `node` is only called if a checker is installed. The lock is released
+    /// before the checker runs, so it may access the filesystem itself.
+    pub fn check_access(
+        &self,
+        node: impl FnOnce() -> Option<VfsNodeRef>,
+        mode: AccessMode,
+    ) -> VfsResult {
+        let checker = self.access_checker.read().clone();
+        if let (Some(checker), Some(node)) = (checker, node()) {
+            if !checker(&node, mode) {
+                return Err(VfsError::PermissionDenied);
+            }
+        }
+        Ok(())
+    }
+
+    /// Allocates a new inode number, unique within the filesystem.
+    pub fn alloc_ino(&self) -> u64 {
+        self.next_ino.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub fn set_time_source(&self, source: Arc<TimeSource>) {
+        *self.time_source.write() = Some(source);
+    }
+
+    /// Returns the current time in nanoseconds, or `0` if no time source is
+    /// set.
+    pub fn now(&self) -> u64 {
+        let source = self.time_source.read().clone();
+        source.map_or(0, |source| source())
+    }
+
+    pub fn set_max_symlink_len(&self, len: usize) {
+        self.max_symlink_len.store(len, Ordering::Relaxed)
+    }
+
+    /// Fails with [`VfsError::InvalidInput`] if `target` is empty or
+    /// contains a NUL byte, and with [`VfsError::NameTooLong`] if it is
+    /// longer than the configured maximum.
+    pub fn check_symlink_target(&self, target: &str) -> VfsResult {
+        if target.is_empty() || target.contains('\0') {
+            return Err(VfsError::InvalidInput);
+        }
+        if target.len() > self.max_symlink_len.load(Ordering::Relaxed) {
+            return Err(VfsError::NameTooLong);
+        }
+        Ok(())
+    }
+
+    pub fn max_dir_entries(&self) -> usize {
+        self.max_dir_entries.load(Ordering::Relaxed)
+    }
+
+    pub fn set_max_dir_entries(&self, max: usize) {
+        self.max_dir_entries.store(max, Ordering::Relaxed)
+    }
+
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    /// Records that a file content changed from `old` to `new` bytes.
+    ///
+    /// Fails with [`VfsError::StorageFull`], recording nothing, if the
+    /// content would grow beyond the capacity of the filesystem.
+    pub fn account(&self, old: usize, new: usize) -> VfsResult {
+        if new <= old {
+            self.release(old - new);
+            return Ok(());
+        }
+        let capacity = self.capacity();
+        let prev = self
+            .memory_usage
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(new - old).filter(|&used| used <= capacity)
+            })
+            .map_err(|_| VfsError::StorageFull)?;
+        self.peak_memory_usage
+            .fetch_max(prev + (new - old), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Records that `size` bytes of file content were freed.
+    pub fn release(&self, size: usize) {
+        self.memory_usage.fetch_sub(size, Ordering::Relaxed);
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage.load(Ordering::Relaxed)
+    }
+
+    pub fn peak_memory_usage(&self) -> usize {
+        self.peak_memory_usage.load(Ordering::Relaxed)
+    }
+
+    pub fn reset_peak(&self) {
+        let used = self.memory_usage();
+        self.peak_memory_usage.store(used, Ordering::Relaxed);
+    }
+
+    fn node_counter(&self, ty: VfsNodeType) -> Option<&AtomicUsize> {
+        match ty {
+            VfsNodeType::File => Some(&self.files),
+            VfsNodeType::Dir => Some(&self.dirs),
+            VfsNodeType::SymLink => Some(&self.symlinks),
+            _ => None,
+        }
+    }
+
+    /// Records that a node of type `ty` was created.
+    pub fn node_created(&self, ty: VfsNodeType) {
+        if let Some(counter) = self.node_counter(ty) {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records that a node of type `ty` was dropped.
+    pub fn node_dropped(&self, ty: VfsNodeType) {
+        if let Some(counter) = self.node_counter(ty) {
+            counter.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> RamFsStats {
+        RamFsStats {
+            total_bytes: self.memory_usage(),
+            files: self.files.load(Ordering::Relaxed),
+            dirs: self.dirs.load(Ordering::Relaxed),
+            symlinks: self.symlinks.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -1,29 +1,138 @@
 use crate::alloc::string::ToString;
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
 use alloc::sync::{Arc, Weak};
-use alloc::{string::String, vec::Vec};
+use alloc::{string::String, vec, vec::Vec};
+use core::any::Any;
+use core::ops::Bound;
+use core::sync::atomic::{AtomicBool, Ordering};
 
-use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType};
 use axfs_vfs::{VfsError, VfsResult};
-use spin::RwLock;
+use spin::{Mutex, RwLock, RwLockWriteGuard};
 
+use crate::context::{AccessMode, FsContext};
+use crate::downcast_node;
+use crate::entries::Entries;
+use crate::fifo::FifoNode;
 use crate::file::FileNode;
+use crate::gen_dir::GenDirNode;
+use crate::listing::{self, ListingEntry};
+use crate::meta::NodeMeta;
+use crate::symlink::SymlinkNode;
+use crate::RamFileSystem;
+
+/// The `(component name, node)` pairs visited by [`DirNode::lookup_trace`].
+pub type LookupChain = Vec<(String, VfsNodeRef)>;
+
+/// The reason of a failed [`DirNode::lookup_detailed`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LookupFailure {
+    /// The component does not exist in its parent directory.
+    NotFound,
+    /// The component was looked up in a node that is not a directory.
+    NotADirectory,
+    /// The component is `..`, but the directory has no parent.
+    NoParent,
+    /// The lookup failed with another error.
+    Other(VfsError),
+}
+
+/// A detailed error returned by [`DirNode::lookup_detailed`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LookupError {
+    /// Index of the failing component, ignoring empty ones.
+    pub index: usize,
+    /// Name of the failing component.
+    pub component: String,
+    /// Why the component could not be resolved.
+    pub reason: LookupFailure,
+}
+
+impl From<LookupError> for VfsError {
+    fn from(err: LookupError) -> Self {
+        match err.reason {
+            LookupFailure::NotFound | LookupFailure::NoParent => VfsError::NotFound,
+            LookupFailure::NotADirectory => VfsError::NotADirectory,
+            LookupFailure::Other(err) => err,
+        }
+    }
+}
+
+/// The outcome of [`DirNode::remove_removable`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RemovalReport {
+    /// Names of the entries that were removed.
+    pub removed: Vec<String>,
+    /// Names of the entries that were left in place, with the reason.
+    pub skipped: Vec<(String, VfsError)>,
+}
+
+/// What [`DirNode::merge_from`] does when both directories have an entry
+/// with the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Keep the entry of the target directory and drop the other one.
+    Skip,
+    /// Replace the entry of the target directory with the other one.
+    Overwrite,
+    /// Fail with [`VfsError::AlreadyExists`] without moving anything.
+    Error,
+}
+
+/// A change of the entries of a directory, reported to the watchers added
+/// with [`DirNode::add_watcher`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsEvent {
+    /// An entry was created, or moved in from another directory.
+    Create(String),
+    /// An entry was removed, or moved out to another directory.
+    Remove(String),
+    /// An entry was renamed within the directory.
+    Rename {
+        /// The old name of the entry.
+        from: String,
+        /// The new name of the entry.
+        to: String,
+    },
+}
+
+type Watcher = dyn Fn(&FsEvent) + Send + Sync;
+
+/// Serializes the operations that lock the entries of two directories, so
+/// the tree cannot be rearranged while [`DirNode::lock_pair`] orders them.
+static MOVE_LOCK: Mutex<()> = Mutex::new(());
 
 /// The directory node in the RAM filesystem.
 ///
 /// It implements [`axfs_vfs::VfsNodeOps`].
 pub struct DirNode {
     this: Weak<DirNode>,
+    ctx: Arc<FsContext>,
+    meta: NodeMeta,
     parent: RwLock<Weak<dyn VfsNodeOps>>,
-    children: RwLock<BTreeMap<String, VfsNodeRef>>,
+    /// Sorted by name, so listings and tree walks are deterministic.
+    children: RwLock<Entries>,
+    allowed_types: RwLock<Option<Vec<VfsNodeType>>>,
+    max_entries: RwLock<Option<usize>>,
+    watchers: RwLock<Vec<Arc<Watcher>>>,
+    readonly: AtomicBool,
 }
 
 impl DirNode {
-    pub(super) fn new(parent: Option<Weak<dyn VfsNodeOps>>) -> Arc<Self> {
+    pub(super) fn new(parent: Option<Weak<dyn VfsNodeOps>>, ctx: Arc<FsContext>) -> Arc<Self> {
+        ctx.node_created(VfsNodeType::Dir);
+        let children = Entries::new(ctx.case_insensitive());
         Arc::new_cyclic(|this| Self {
             this: this.clone(),
+            meta: NodeMeta::new(ctx.alloc_ino(), VfsNodePerm::default_dir(), ctx.now()),
+            ctx,
             parent: RwLock::new(parent.unwrap_or_else(|| Weak::<Self>::new())),
-            children: RwLock::new(BTreeMap::new()),
+            children: RwLock::new(children),
+            allowed_types: RwLock::new(None),
+            max_entries: RwLock::new(None),
+            watchers: RwLock::new(Vec::new()),
+            readonly: AtomicBool::new(false),
         })
     }
 
@@ -31,11 +140,323 @@ impl DirNode {
         *self.parent.write() = parent.map_or(Weak::<Self>::new() as _, Arc::downgrade);
     }
 
+    /// Returns the metadata version of the directory.
+    ///
+    /// The version is bumped on every metadata change, including content
+    /// modifications and extended attributes, except access time updates.
+    pub fn version(&self) -> u64 {
+        self.meta.version()
+    }
+
+    /// Attaches arbitrary user data to the directory, replacing any previous one.
+    pub fn set_user_data(&self, data: Arc<dyn Any + Send + Sync>) {
+        self.meta.set_user_data(data)
+    }
+
+    /// Returns the user data attached to the directory, if any.
+    ///
+    /// Use [`Arc::downcast`] to get the concrete type back.
+    pub fn user_data(&self) -> Option<Arc<dyn Any + Send + Sync>> {
+        self.meta.user_data()
+    }
+
+    /// Applies the metadata in `attr` only if the current metadata version is
+    /// `expected_version`, and returns the new version.
+    ///
+    /// Returns [`VfsError::ResourceBusy`] if the version does not match, i.e.
+    /// the metadata has been changed concurrently.
+    pub fn compare_and_set_attr(&self, expected_version: u64, attr: VfsNodeAttr) -> VfsResult<u64> {
+        self.check_writable()?;
+        self.meta
+            .compare_and_set_attr(expected_version, &attr, self.ctx.now())
+    }
+
+    /// Changes the owner of the directory, like `chown`.
+    ///
+    /// A `None` user or group ID is left unchanged.
+    pub fn chown(&self, uid: Option<u32>, gid: Option<u32>) -> VfsResult {
+        self.check_writable()?;
+        self.meta.chown(uid, gid, self.ctx.now());
+        Ok(())
+    }
+
+    /// Restricts the types of nodes that can be created in or added to this
+    /// directory to `types`.
+    ///
+    /// Creating a node of any other type fails with
+    /// [`VfsError::PermissionDenied`]. Existing entries are not affected.
+    pub fn set_allowed_child_types(&self, types: &[VfsNodeType]) {
+        *self.allowed_types.write() = Some(types.to_vec());
+    }
+
+    /// Lifts the restriction set by
+    /// [`set_allowed_child_types`](Self::set_allowed_child_types).
+    pub fn allow_all_child_types(&self) {
+        *self.allowed_types.write() = None;
+    }
+
+    fn check_child_type(&self, ty: VfsNodeType) -> VfsResult {
+        self.ctx.check_node_type(ty)?;
+        match self.allowed_types.read().as_deref() {
+            Some(types) if !types.contains(&ty) => Err(VfsError::PermissionDenied),
+            _ => Ok(()),
+        }
+    }
+
+    /// Limits the number of entries of this directory to `max`, or to the
+    /// limit of the filesystem if `None`.
+    ///
+    /// Adding an entry to a full directory fails with
+    /// [`VfsError::StorageFull`]. Existing entries are not affected, and
+    /// replacing an entry is always allowed.
+    pub fn set_max_entries(&self, max: Option<usize>) {
+        *self.max_entries.write() = max;
+    }
+
+    /// Fails with [`VfsError::StorageFull`] if adding `added` entries to
+    /// `children`, the locked entries of this directory, would exceed the
+    /// entry limit.
+    fn check_room(&self, children: &Entries, added: usize) -> VfsResult {
+        let max = self
+            .max_entries
+            .read()
+            .unwrap_or_else(|| self.ctx.max_dir_entries());
+        match children.len().saturating_add(added) > max {
+            true => Err(VfsError::StorageFull),
+            false => Ok(()),
+        }
+    }
+
+    /// Marks this directory and all its descendants as read-only, or lifts
+    /// the mark.
+    ///
+    /// While marked, creating, removing, or renaming entries under this
+    /// directory, and writing or truncating files in it, fail with
+    /// [`VfsError::ReadOnlyFilesystem`]. The rest of the filesystem is not
+    /// affected.
+    pub fn set_subtree_readonly(&self, readonly: bool) {
+        self.readonly.store(readonly, Ordering::Release);
+    }
+
+    /// Records that the entries of this directory changed.
+    fn touch(&self) {
+        self.meta.touch_modified(self.ctx.now());
+    }
+
+    /// Registers `watcher` to be called on every entry created in, removed
+    /// from, or renamed in this directory, but not in its subdirectories.
+    ///
+    /// Watchers are called after the change is made and the directory is
+    /// unlocked, so they may access the directory.
+    pub fn add_watcher(&self, watcher: impl Fn(&FsEvent) + Send + Sync + 'static) {
+        self.watchers.write().push(Arc::new(watcher));
+    }
+
+    /// Removes all watchers added with [`add_watcher`](Self::add_watcher).
+    pub fn clear_watchers(&self) {
+        self.watchers.write().clear();
+    }
+
+    /// Reports the event built by `event` to the watchers, if there are any.
+    ///
+    /// Must not be called with the entries locked.
+    fn notify(&self, event: impl FnOnce() -> FsEvent) {
+        let watchers = self.watchers.read().clone();
+        if !watchers.is_empty() {
+            let event = event();
+            watchers.iter().for_each(|watcher| watcher(&event));
+        }
+    }
+
+    fn check_access(&self, mode: AccessMode) -> VfsResult {
+        self.ctx
+            .check_access(|| self.this.upgrade().map(|n| n as VfsNodeRef), mode)
+    }
+
+    /// Fails if this directory or any of its ancestors in the same filesystem
+    /// is marked read-only.
+    pub(crate) fn check_writable(&self) -> VfsResult {
+        if self.ctx.readonly() || self.readonly.load(Ordering::Acquire) {
+            return Err(VfsError::ReadOnlyFilesystem);
+        }
+        let mut cur = self.parent();
+        while let Some(node) = cur {
+            match node.as_any().downcast_ref::<DirNode>() {
+                Some(dir) if Arc::ptr_eq(&dir.ctx, &self.ctx) => {
+                    if dir.readonly.load(Ordering::Acquire) {
+                        return Err(VfsError::ReadOnlyFilesystem);
+                    }
+                }
+                _ => break,
+            }
+            cur = node.parent();
+        }
+        Ok(())
+    }
+
     /// Returns a string list of all entries in this directory.
     pub fn get_entries(&self) -> Vec<String> {
         self.children.read().keys().cloned().collect()
     }
 
+    /// Returns the name and type of all entries in this directory.
+    ///
+    /// Entries whose attributes cannot be read are skipped.
+    pub fn get_entries_with_type(&self) -> Vec<(String, VfsNodeType)> {
+        self.children
+            .read()
+            .iter()
+            .filter_map(|(name, node)| Some((name.clone(), node_type(node).ok()?)))
+            .collect()
+    }
+
+    /// Lists the entries whose names sort after `after`, or all entries if
+    /// `None`, in name order and without `.` and `..`, and returns the number
+    /// of entries filled in.
+    ///
+    /// Passing the name of the last entry listed resumes the listing right
+    /// after it, so unlike with [`read_dir`](VfsNodeOps::read_dir) no entry
+    /// that exists throughout the listing is skipped or listed twice, however
+    /// the directory changes between calls.
+    pub fn read_dir_after(
+        &self,
+        after: Option<&str>,
+        dirents: &mut [VfsDirEntry],
+    ) -> VfsResult<usize> {
+        self.check_access(AccessMode::Read)?;
+        self.meta.touch_accessed(self.ctx.now());
+        let children = self.children.read();
+        let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+        let mut count = 0;
+        for (ent, (name, node)) in dirents
+            .iter_mut()
+            .zip(children.range::<str, _>((start, Bound::Unbounded)))
+        {
+            *ent = VfsDirEntry::new(name, node_type(node)?);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Returns the name and attributes of all entries in this directory,
+    /// sorted by name.
+    ///
+    /// Entries whose attributes cannot be read are skipped with a warning.
+    /// The attributes are read after the directory is unlocked.
+    pub fn list_sorted(&self) -> Vec<(String, VfsNodeAttr)> {
+        let children: Vec<_> = self
+            .children
+            .read()
+            .iter()
+            .map(|(name, node)| (name.clone(), node.clone()))
+            .collect();
+        children
+            .into_iter()
+            .filter_map(|(name, node)| match node.get_attr() {
+                Ok(attr) => Some((name, attr)),
+                Err(err) => {
+                    log::warn!("skipping {name}: {err:?}");
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the name of the entry of this directory that refers to
+    /// `child`, if any.
+    ///
+    /// If several entries refer to it, the first one in name order is
+    /// returned.
+    pub fn name_of(&self, child: &VfsNodeRef) -> Option<String> {
+        self.children
+            .read()
+            .iter()
+            .find(|(_, node)| Arc::ptr_eq(node, child))
+            .map(|(name, _)| name.clone())
+    }
+
+    /// Returns the number of entries in this directory, not counting `.`
+    /// and `..`.
+    pub fn len(&self) -> usize {
+        self.children.read().len()
+    }
+
+    /// Checks whether this directory has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.children.read().is_empty()
+    }
+
+    /// Creates a new filesystem whose root directory holds the same tree as
+    /// this directory.
+    ///
+    /// The directories and files are copied into the new filesystem, like
+    /// [`RamFileSystem::snapshot`] does, so paths, settings and statistics
+    /// of the new filesystem do not reach into this one, and vice versa. The
+    /// content of each file is shared until either copy is written.
+    pub fn into_subfs(&self) -> VfsResult<RamFileSystem> {
+        let fs = RamFileSystem::new();
+        let root = fs.root_dir_node();
+        root.copy_attr(&self.get_attr()?);
+        self.copy_into(&root)?;
+        Ok(fs)
+    }
+
+    /// Returns the entry with the smallest name in this directory, if any.
+    ///
+    /// Entries are ordered by the bytes of their names, which is also the
+    /// order of [`get_entries`](Self::get_entries) and `read_dir`.
+    pub fn first_entry(&self) -> Option<(String, VfsNodeRef)> {
+        let children = self.children.read();
+        let (name, node) = children.first_key_value()?;
+        Some((name.clone(), node.clone()))
+    }
+
+    /// Returns the entry with the largest name in this directory, if any.
+    ///
+    /// See [`first_entry`](Self::first_entry) for the ordering.
+    pub fn last_entry(&self) -> Option<(String, VfsNodeRef)> {
+        let children = self.children.read();
+        let (name, node) = children.last_key_value()?;
+        Some((name.clone(), node.clone()))
+    }
+
+    /// Returns the names of the entries in this directory for which `pred`
+    /// returns `true`.
+    ///
+    /// `pred` runs under the read lock of this directory, so it must not
+    /// modify it.
+    pub fn entries_matching(&self, pred: impl Fn(&str) -> bool) -> Vec<String> {
+        self.children
+            .read()
+            .keys()
+            .filter(|name| pred(name))
+            .cloned()
+            .collect()
+    }
+
+    /// Encodes the entries of this directory, without `.` and `..`, in the
+    /// binary format described in [`decode_listing`](crate::decode_listing).
+    ///
+    /// Entries whose attributes cannot be retrieved are skipped. Returns
+    /// [`VfsError::NameTooLong`] if a name is longer than `u16::MAX` bytes.
+    pub fn encode_listing(&self) -> VfsResult<Vec<u8>> {
+        let entries: Vec<_> = self
+            .children
+            .read()
+            .iter()
+            .filter_map(|(name, node)| {
+                let attr = node.get_attr().ok()?;
+                Some(ListingEntry {
+                    name: name.clone(),
+                    ty: attr.file_type(),
+                    size: attr.size(),
+                    ino: attr.ino(),
+                })
+            })
+            .collect();
+        listing::encode(&entries)
+    }
+
     /// Checks whether a node with the given name exists in this directory.
     pub fn exist(&self, name: &str) -> bool {
         self.children.read().contains_key(name)
@@ -43,34 +464,1088 @@ impl DirNode {
 
     /// Creates a new node with the given name and type in this directory.
     pub fn create_node(&self, name: &str, ty: VfsNodeType) -> VfsResult {
-        if self.exist(name) {
-            log::error!("AlreadyExists {name}");
-            return Err(VfsError::AlreadyExists);
+        self.create_child(name, ty).map(|_| ())
+    }
+
+    /// Creates a node like [`create`](VfsNodeOps::create), and returns it.
+    ///
+    /// If the last component of `path` is empty, `.` or `..`, nothing is
+    /// created and the existing node it refers to is returned.
+    pub fn create_and_get(&self, path: &str, ty: VfsNodeType) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        if let Some(rest) = rest {
+            let node = self.descend_for_create(name)?;
+            match node.as_any().downcast_ref::<DirNode>() {
+                Some(dir) => dir.create_and_get(rest, ty),
+                None => {
+                    node.create(rest, ty)?;
+                    node.lookup(rest)
+                }
+            }
+        } else if name.is_empty() || name == "." || name == ".." {
+            self.traverse_path(name)
+        } else {
+            self.create_child(name, ty)
         }
+    }
+
+    /// Returns the entry `name` that a path being created goes through,
+    /// creating it as a directory if it is missing and the filesystem has
+    /// [`auto_mkdir`](crate::RamFileSystem::set_auto_mkdir) enabled.
+    fn descend_for_create(&self, name: &str) -> VfsResult<VfsNodeRef> {
+        match self.traverse_path(name) {
+            Err(VfsError::NotFound) if self.ctx.auto_mkdir() => {
+                match self.create_node(name, VfsNodeType::Dir) {
+                    Ok(()) | Err(VfsError::AlreadyExists) => {}
+                    Err(err) => return Err(err),
+                }
+                self.traverse_path(name)
+            }
+            res => res,
+        }
+    }
+
+    fn create_child(&self, name: &str, ty: VfsNodeType) -> VfsResult<VfsNodeRef> {
+        self.check_writable()?;
+        self.check_child_type(ty)?;
         let node: VfsNodeRef = match ty {
-            VfsNodeType::File => Arc::new(FileNode::new()),
-            VfsNodeType::Dir => Self::new(Some(self.this.clone())),
+            VfsNodeType::File => FileNode::new(self.this.clone(), self.ctx.clone()),
+            VfsNodeType::Dir => Self::new(Some(self.this.clone()), self.ctx.clone()),
+            VfsNodeType::Fifo => FifoNode::new(self.this.clone(), self.ctx.clone()),
             _ => return Err(VfsError::Unsupported),
         };
-        self.children.write().insert(name.into(), node);
+        let mut children = self.children.write();
+        if children.contains_key(name) {
+            log::error!("AlreadyExists {name}");
+            return Err(VfsError::AlreadyExists);
+        }
+        self.check_room(&children, 1)?;
+        children.insert(name.into(), node.clone());
+        drop(children);
+        self.touch();
+        self.notify(|| FsEvent::Create(name.into()));
+        Ok(node)
+    }
+
+    /// Creates a new node like [`create_node`](Self::create_node), but tells
+    /// apart name collisions with a node of a conflicting type.
+    ///
+    /// Creating a file where a directory exists fails with
+    /// [`VfsError::IsADirectory`], and a directory where a non-directory
+    /// exists with [`VfsError::NotADirectory`]. Other collisions fail with
+    /// [`VfsError::AlreadyExists`].
+    pub fn create_typed(&self, name: &str, ty: VfsNodeType) -> VfsResult {
+        let existing = self.children.read().get(name).cloned();
+        if let Some(node) = existing {
+            let is_dir = node.get_attr()?.is_dir();
+            return Err(match (is_dir, ty.is_dir()) {
+                (true, false) => VfsError::IsADirectory,
+                (false, true) => VfsError::NotADirectory,
+                _ => VfsError::AlreadyExists,
+            });
+        }
+        self.create_node(name, ty)
+    }
+
+    /// Creates a node of type `ty` at `path`, creating the missing
+    /// intermediate directories like `mkdir -p`.
+    ///
+    /// Existing intermediate directories are used as they are, but an
+    /// intermediate component that is not a directory fails with
+    /// [`VfsError::NotADirectory`]. If the last component exists, this fails
+    /// with [`VfsError::AlreadyExists`], unless both it and `ty` are
+    /// directories.
+    pub fn create_all(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        let path = path.trim_matches('/');
+        let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let dir = this.create_dir_all(dir)?;
+        if name.is_empty() || name == "." || name == ".." {
+            return match ty {
+                VfsNodeType::Dir => Ok(()),
+                _ => Err(VfsError::AlreadyExists),
+            };
+        }
+        match dir.create_node(name, ty) {
+            Err(VfsError::AlreadyExists) if ty == VfsNodeType::Dir => {
+                as_dir(dir.traverse_path(name)?).map(|_| ())
+            }
+            res => res,
+        }
+    }
+
+    /// Creates a file with the given name and content in this directory, and
+    /// returns it.
+    ///
+    /// Like [`create_node`](Self::create_node), this fails with
+    /// [`VfsError::AlreadyExists`] if the name is taken.
+    pub fn create_file_with(&self, name: &str, contents: &[u8]) -> VfsResult<Arc<FileNode>> {
+        self.create_file(name, contents.to_vec())
+    }
+
+    /// Creates an empty file for each of `names` in this directory, under a
+    /// single acquisition of the write lock.
+    ///
+    /// Nothing is created if any of the names already exists or appears
+    /// twice, in which case [`VfsError::AlreadyExists`] is returned.
+    pub fn create_files(&self, names: &[&str]) -> VfsResult {
+        self.check_writable()?;
+        self.check_child_type(VfsNodeType::File)?;
+        let mut children = self.children.write();
+        let mut seen = BTreeSet::new();
+        for &name in names {
+            if children.contains_key(name) || !seen.insert(name) {
+                return Err(VfsError::AlreadyExists);
+            }
+        }
+        self.check_room(&children, names.len())?;
+        for &name in names {
+            let file = FileNode::new(self.this.clone(), self.ctx.clone());
+            children.insert(name.into(), file);
+        }
+        drop(children);
+        self.touch();
+        for &name in names {
+            self.notify(|| FsEvent::Create(name.into()));
+        }
         Ok(())
     }
 
+    /// Returns the file with the given name, or creates it with the contents
+    /// produced by `init` if it does not exist yet.
+    ///
+    /// The existence check and the creation happen under the same write lock,
+    /// so `init` runs at most once even if several callers race on the same
+    /// name. `init` must not access this directory, or it will deadlock.
+    ///
+    /// Returns [`VfsError::AlreadyExists`] if the name is taken by a node
+    /// that is not a [`FileNode`].
+    pub fn get_or_init_file(
+        &self,
+        name: &str,
+        init: impl FnOnce() -> Vec<u8>,
+    ) -> VfsResult<Arc<FileNode>> {
+        self.check_writable()?;
+        self.check_child_type(VfsNodeType::File)?;
+        let mut children = self.children.write();
+        if let Some(node) = children.get(name) {
+            return downcast_node(node.clone()).ok_or(VfsError::AlreadyExists);
+        }
+        self.check_room(&children, 1)?;
+        let file = FileNode::with_content(self.this.clone(), self.ctx.clone(), init())?;
+        children.insert(name.into(), file.clone());
+        drop(children);
+        self.touch();
+        self.notify(|| FsEvent::Create(name.into()));
+        Ok(file)
+    }
+
+    /// Adds `target` to this directory as a hard link named `name`.
+    ///
+    /// The existence check and the insertion happen under the same write
+    /// lock. Returns [`VfsError::AlreadyExists`] if the name is taken, and
+    /// [`VfsError::PermissionDenied`] if `target` is not a regular file, as
+    /// only files have their links counted.
+    pub fn link_exclusive(&self, name: &str, target: VfsNodeRef) -> VfsResult {
+        self.check_writable()?;
+        let ty = target.get_attr()?.file_type();
+        if !ty.is_file() {
+            return Err(VfsError::PermissionDenied);
+        }
+        self.check_child_type(ty)?;
+        let mut children = self.children.write();
+        if children.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        self.check_room(&children, 1)?;
+        count_link(&target, true);
+        children.insert(name.into(), target);
+        drop(children);
+        self.touch();
+        self.notify(|| FsEvent::Create(name.into()));
+        Ok(())
+    }
+
+    /// Creates a hard link named `new_name` in this directory to `target`.
+    ///
+    /// Both names then refer to the same node, whose link count is
+    /// incremented. The content is only freed once all names are removed.
+    /// Returns [`VfsError::AlreadyExists`] if the name is taken, and
+    /// [`VfsError::PermissionDenied`] if `target` is not a regular file.
+    pub fn link_node(&self, new_name: &str, target: &VfsNodeRef) -> VfsResult {
+        self.link_exclusive(new_name, target.clone())
+    }
+
     /// Removes a node by the given name in this directory.
     pub fn remove_node(&self, name: &str) -> VfsResult {
+        self.check_writable()?;
         let mut children = self.children.write();
         let node = children.get(name).ok_or(VfsError::NotFound)?;
         if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
-            if !dir.children.read().is_empty() {
+            if !dir.is_empty() {
                 return Err(VfsError::DirectoryNotEmpty);
             }
         }
+        if let Some(node) = children.remove(name) {
+            count_link(&node, false);
+        }
+        drop(children);
+        self.touch();
+        self.notify(|| FsEvent::Remove(name.into()));
+        Ok(())
+    }
+
+    /// Moves all entries of `other` into this directory, leaving `other`
+    /// empty.
+    ///
+    /// Name collisions are resolved according to `on_conflict`. Fails with
+    /// [`VfsError::InvalidInput`] if this directory is `other` or inside it.
+    pub fn merge_from(&self, other: &Arc<DirNode>, on_conflict: ConflictPolicy) -> VfsResult {
+        self.check_writable()?;
+        other.check_writable()?;
+        if self.is_descendant_of(&(other.clone() as VfsNodeRef)) {
+            return Err(VfsError::InvalidInput);
+        }
+        let _serial = MOVE_LOCK.lock();
+        let (mut children, mut other_children) = Self::lock_pair(self, other);
+        for (name, node) in other_children.iter() {
+            self.check_child_type(node.get_attr()?.file_type())?;
+            if on_conflict == ConflictPolicy::Error && children.contains_key(name) {
+                return Err(VfsError::AlreadyExists);
+            }
+        }
+        let added = other_children
+            .keys()
+            .filter(|name| !children.contains_key(name))
+            .count();
+        self.check_room(&children, added)?;
+        let mut moved = Vec::new();
+        for (name, node) in other_children.take() {
+            if on_conflict == ConflictPolicy::Skip && children.contains_key(&name) {
+                count_link(&node, false);
+                moved.push((name, false));
+                continue;
+            }
+            self.adopt(&node);
+            if let Some(old) = children.insert(name.clone(), node) {
+                count_link(&old, false);
+            }
+            moved.push((name, true));
+        }
+        drop((children, other_children));
+        self.touch();
+        other.touch();
+        for (name, added) in moved {
+            other.notify(|| FsEvent::Remove(name.clone()));
+            if added {
+                self.notify(|| FsEvent::Create(name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the entry `old_name` of this directory to `new_name` in
+    /// `new_dir`, which may be this directory.
+    ///
+    /// Returns [`VfsError::NotFound`] if `old_name` does not exist,
+    /// [`VfsError::AlreadyExists`] if `new_name` already exists in `new_dir`,
+    /// and [`VfsError::InvalidInput`] when moving a directory into itself or
+    /// one of its descendants.
+    pub fn rename(&self, old_name: &str, new_dir: &Arc<DirNode>, new_name: &str) -> VfsResult {
+        let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        Self::move_node(&this, old_name, new_dir, new_name, false, None)
+    }
+
+    /// Exchanges the nodes of the entries `name_a` and `name_b`.
+    ///
+    /// Both entries are replaced under a single lock, so a concurrent lookup
+    /// finds either the old or the new node under each name, never none.
+    /// Returns [`VfsError::NotFound`] if either name does not exist.
+    ///
+    /// Watchers see a [`FsEvent::Rename`] for each direction.
+    pub fn swap(&self, name_a: &str, name_b: &str) -> VfsResult {
+        self.check_writable()?;
+        let mut children = self.children.write();
+        let a = children.get(name_a).cloned().ok_or(VfsError::NotFound)?;
+        let b = children.get(name_b).cloned().ok_or(VfsError::NotFound)?;
+        if children.same_entry(name_a, name_b) {
+            return Ok(());
+        }
+        children.insert(name_a.into(), b);
+        children.insert(name_b.into(), a);
+        drop(children);
+        self.touch();
+        self.notify(|| FsEvent::Rename {
+            from: name_a.into(),
+            to: name_b.into(),
+        });
+        self.notify(|| FsEvent::Rename {
+            from: name_b.into(),
+            to: name_a.into(),
+        });
+        Ok(())
+    }
+
+    /// Removes the entry `name` and, if it is a directory, everything below
+    /// it, and returns the number of nodes removed.
+    ///
+    /// Descendant directories are emptied depth-first, so the removed nodes
+    /// are freed promptly unless referenced elsewhere. Fails with
+    /// [`VfsError::ReadOnlyFilesystem`], removing nothing, if any directory
+    /// in the subtree is marked read-only.
+    pub fn remove_all(&self, name: &str) -> VfsResult<usize> {
+        self.check_writable()?;
+        let node = self.children.read().get(name).cloned();
+        let node = node.ok_or(VfsError::NotFound)?;
+        if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+            let mut readonly = dir.readonly.load(Ordering::Acquire);
+            dir.walk(&mut |_, node| {
+                if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+                    readonly |= dir.readonly.load(Ordering::Acquire);
+                }
+            });
+            if readonly {
+                return Err(VfsError::ReadOnlyFilesystem);
+            }
+        }
+        let mut children = self.children.write();
+        if !children
+            .get(name)
+            .is_some_and(|cur| Arc::ptr_eq(cur, &node))
+        {
+            drop(children);
+            return self.remove_all(name); // replaced while checking, start over
+        }
         children.remove(name);
+        drop(children);
+        count_link(&node, false);
+        self.touch();
+        self.notify(|| FsEvent::Remove(name.into()));
+        Ok(1 + node
+            .as_any()
+            .downcast_ref::<DirNode>()
+            .map_or(0, DirNode::clear))
+    }
+
+    /// Removes all entries below this directory, depth-first, and returns
+    /// the number of nodes removed.
+    fn clear(&self) -> usize {
+        let children = self.children.write().take();
+        let mut count = 0;
+        for node in children.into_values() {
+            if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+                count += dir.clear();
+            }
+            count_link(&node, false);
+            count += 1;
+        }
+        count
+    }
+
+    /// Removes all entries whose names start with `prefix`, under a single
+    /// lock, and returns the number of entries removed.
+    ///
+    /// Non-empty directories are skipped and left in place, like the entries
+    /// [`remove_removable`](Self::remove_removable) cannot remove.
+    pub fn remove_prefix(&self, prefix: &str) -> VfsResult<usize> {
+        self.check_writable()?;
+        let mut children = self.children.write();
+        let names: Vec<String> = children
+            .with_prefix(prefix)
+            .into_iter()
+            .filter(|(_, node)| {
+                node.as_any()
+                    .downcast_ref::<DirNode>()
+                    .is_none_or(DirNode::is_empty)
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in &names {
+            if let Some(node) = children.remove(name) {
+                count_link(&node, false);
+            }
+        }
+        drop(children);
+        if !names.is_empty() {
+            self.touch();
+        }
+        let count = names.len();
+        for name in names {
+            self.notify(|| FsEvent::Remove(name));
+        }
+        Ok(count)
+    }
+
+    /// Removes as many of the named entries as possible.
+    ///
+    /// Unlike [`remove_node`](Self::remove_node), an entry that cannot be
+    /// removed (e.g. a non-empty directory, or one in a read-only subtree)
+    /// does not stop the others from being removed; it is reported in
+    /// [`RemovalReport::skipped`] instead.
+    pub fn remove_removable(&self, names: &[&str]) -> RemovalReport {
+        let mut report = RemovalReport::default();
+        for &name in names {
+            match self.remove_node(name) {
+                Ok(()) => report.removed.push(name.into()),
+                Err(err) => report.skipped.push((name.into(), err)),
+            }
+        }
+        report
+    }
+
+    /// Looks up `path` like [`lookup`](VfsNodeOps::lookup), but returns every
+    /// intermediate component and the node it resolved to.
+    ///
+    /// On failure, the components resolved so far are returned along with
+    /// the error, so the last entry of the chain shows where resolution
+    /// stopped.
+    pub fn lookup_trace(
+        self: Arc<Self>,
+        path: &str,
+    ) -> Result<LookupChain, (LookupChain, VfsError)> {
+        let mut chain = Vec::new();
+        match self.lookup_components(path, |name, node| chain.push((name.into(), node.clone()))) {
+            Ok(_) => Ok(chain),
+            Err((_, _, err)) => Err((chain, err)),
+        }
+    }
+
+    /// Looks up `path` like [`lookup`](VfsNodeOps::lookup), but on failure
+    /// reports which component failed and why.
+    pub fn lookup_detailed(self: Arc<Self>, path: &str) -> Result<VfsNodeRef, LookupError> {
+        self.lookup_components(path, |_, _| {})
+            .map_err(|(index, name, err)| LookupError {
+                index,
+                component: name.into(),
+                reason: match err {
+                    VfsError::NotFound if name == ".." => LookupFailure::NoParent,
+                    VfsError::NotFound => LookupFailure::NotFound,
+                    VfsError::NotADirectory => LookupFailure::NotADirectory,
+                    err => LookupFailure::Other(err),
+                },
+            })
+    }
+
+    /// Resolves `path` one component at a time, calling `f` with each
+    /// component and the node it resolved to.
+    ///
+    /// On failure, returns the index and name of the failing component along
+    /// with the error.
+    fn lookup_components(
+        self: Arc<Self>,
+        path: &str,
+        mut f: impl FnMut(&str, &VfsNodeRef),
+    ) -> Result<VfsNodeRef, (usize, &str, VfsError)> {
+        let mut cur: VfsNodeRef = self;
+        for (index, name) in path.split('/').filter(|name| !name.is_empty()).enumerate() {
+            cur = cur.lookup(name).map_err(|err| (index, name, err))?;
+            f(name, &cur);
+        }
+        Ok(cur)
+    }
+
+    /// Looks up `path` like [`lookup`](VfsNodeOps::lookup), but follows
+    /// symbolic links found along the way, including the last component.
+    ///
+    /// Relative link targets are resolved against the directory containing
+    /// the link, and absolute ones against the root of this filesystem.
+    /// Following more than `max_depth` links in total fails with
+    /// [`VfsError::FilesystemLoop`], so cyclic links cannot hang the lookup.
+    pub fn lookup_follow(self: Arc<Self>, path: &str, max_depth: usize) -> VfsResult<VfsNodeRef> {
+        let mut links = 0;
+        self.follow(self.clone(), path, max_depth, &mut links)
+    }
+
+    fn follow(
+        self: &Arc<Self>,
+        start: VfsNodeRef,
+        path: &str,
+        max_depth: usize,
+        links: &mut usize,
+    ) -> VfsResult<VfsNodeRef> {
+        let mut cur = match path.starts_with('/') {
+            true => self.fs_root(),
+            false => start,
+        };
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let node = cur.clone().lookup(name)?;
+            cur = match node.as_any().downcast_ref::<SymlinkNode>() {
+                Some(link) => {
+                    *links += 1;
+                    if *links > max_depth {
+                        return Err(VfsError::FilesystemLoop);
+                    }
+                    self.follow(cur, &link.target()?, max_depth, links)?
+                }
+                None => node,
+            };
+        }
+        Ok(cur)
+    }
+
+    /// Returns the root directory of the filesystem this directory is in.
+    fn fs_root(self: &Arc<Self>) -> VfsNodeRef {
+        let mut root = self.clone();
+        while let Some(parent) = root.parent().and_then(downcast_node::<Self>) {
+            if !Arc::ptr_eq(&parent.ctx, &self.ctx) {
+                break; // the mount point in the parent filesystem
+            }
+            root = parent;
+        }
+        root
+    }
+
+    /// Moves the entry `src_name` of `src` to `dst_name` in `dst`.
+    ///
+    /// If `replace` is set, an existing destination entry of a compatible
+    /// type is replaced, otherwise [`VfsError::AlreadyExists`] is returned.
+    ///
+    /// If `fill` is given, it takes the place of the moved entry in `src`
+    /// under the same lock, so `src_name` never disappears. Only a file can
+    /// be moved this way.
+    pub(crate) fn move_node(
+        src: &Arc<Self>,
+        src_name: &str,
+        dst: &Arc<Self>,
+        dst_name: &str,
+        replace: bool,
+        fill: Option<VfsNodeRef>,
+    ) -> VfsResult {
+        src.check_writable()?;
+        dst.check_writable()?;
+        let _serial = (!Arc::ptr_eq(src, dst)).then(|| MOVE_LOCK.lock());
+        let (mut src_children, mut dst_children_guard) = match Arc::ptr_eq(src, dst) {
+            true => (src.children.write(), None),
+            false => {
+                let (src_children, dst_children) = Self::lock_pair(src, dst);
+                (src_children, Some(dst_children))
+            }
+        };
+        let node = src_children
+            .get(src_name)
+            .cloned()
+            .ok_or(VfsError::NotFound)?;
+        if Arc::ptr_eq(src, dst) && src_children.same_entry(src_name, dst_name) {
+            if fill.is_some() {
+                return Err(VfsError::InvalidInput);
+            }
+            src_children.insert(dst_name.into(), node); // at most the casing changes
+            drop(src_children);
+            src.notify(|| FsEvent::Rename {
+                from: src_name.into(),
+                to: dst_name.into(),
+            });
+            return Ok(());
+        }
+        let moved_dir = node.as_any().downcast_ref::<DirNode>();
+        if fill.is_some() && moved_dir.is_some() {
+            return Err(VfsError::IsADirectory);
+        }
+        if moved_dir.is_some() && dst.is_descendant_of(&node) {
+            return Err(VfsError::InvalidInput); // cannot move a directory into itself
+        }
+        if !Arc::ptr_eq(src, dst) {
+            dst.check_child_type(node.get_attr()?.file_type())?;
+        }
+
+        let same_dir = dst_children_guard.is_none();
+        let dst_children = dst_children_guard
+            .as_deref_mut()
+            .unwrap_or(&mut src_children);
+        if let Some(old) = dst_children.get(dst_name) {
+            if !replace {
+                return Err(VfsError::AlreadyExists);
+            }
+            if Arc::ptr_eq(old, &node) && fill.is_none() {
+                return Ok(()); // both names refer to the same node
+            }
+            match (moved_dir.is_some(), old.as_any().downcast_ref::<DirNode>()) {
+                // `old_dir` is not empty if it contains `src`, whose entries
+                // are already locked
+                (true, Some(old_dir)) if src.is_descendant_of(old) || !old_dir.is_empty() => {
+                    return Err(VfsError::DirectoryNotEmpty);
+                }
+                (false, Some(_)) => return Err(VfsError::IsADirectory),
+                (true, None) => return Err(VfsError::NotADirectory),
+                _ => {}
+            }
+        } else if !same_dir || fill.is_some() {
+            dst.check_room(dst_children, 1)?;
+        }
+        count_link(&node, true);
+        if let Some(old) = dst_children.insert(dst_name.into(), node.clone()) {
+            count_link(&old, false);
+        }
+        drop(dst_children_guard);
+        let filled = fill.is_some();
+        match fill {
+            Some(fill) => src_children.insert(src_name.into(), fill),
+            None => src_children.remove(src_name),
+        };
+        drop(src_children);
+        count_link(&node, false);
+
+        dst.adopt(&node);
+        src.touch();
+        dst.touch();
+        match (same_dir, filled) {
+            (true, false) => src.notify(|| FsEvent::Rename {
+                from: src_name.into(),
+                to: dst_name.into(),
+            }),
+            (false, false) => {
+                src.notify(|| FsEvent::Remove(src_name.into()));
+                dst.notify(|| FsEvent::Create(dst_name.into()));
+            }
+            (_, true) => dst.notify(|| FsEvent::Create(dst_name.into())),
+        }
         Ok(())
     }
 
+    /// Write-locks the entries of two different directories, ancestor
+    /// first, as the entries of a subdirectory are read under the lock of
+    /// its parent (e.g. to count its links in [`get_attr`](VfsNodeOps::get_attr)).
+    /// Unrelated directories are locked in address order.
+    ///
+    /// The caller must hold [`MOVE_LOCK`], so no other pair is locked at the
+    /// same time and the ancestry cannot change meanwhile.
+    fn lock_pair<'a>(
+        a: &'a Self,
+        b: &'a Self,
+    ) -> (RwLockWriteGuard<'a, Entries>, RwLockWriteGuard<'a, Entries>) {
+        let is_below = |x: &Self, y: &Self| {
+            y.this
+                .upgrade()
+                .is_some_and(|y| x.is_descendant_of(&(y as VfsNodeRef)))
+        };
+        let a_first = if is_below(b, a) {
+            true
+        } else if is_below(a, b) {
+            false
+        } else {
+            (a as *const Self) < (b as *const Self)
+        };
+        if a_first {
+            let a = a.children.write();
+            (a, b.children.write())
+        } else {
+            let b = b.children.write();
+            (a.children.write(), b)
+        }
+    }
+
+    /// Points the parent reference of `node`, which has just been moved into
+    /// this directory, to this directory.
+    fn adopt(&self, node: &VfsNodeRef) {
+        if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+            let this = self.this.upgrade().map(|this| this as VfsNodeRef);
+            dir.set_parent(this.as_ref());
+        } else if let Some(file) = node.as_any().downcast_ref::<FileNode>() {
+            file.set_dir(self.this.clone());
+        } else if let Some(fifo) = node.as_any().downcast_ref::<FifoNode>() {
+            fifo.set_dir(self.this.clone());
+        } else if let Some(gen) = node.as_any().downcast_ref::<GenDirNode>() {
+            gen.set_parent(self.this.clone());
+        }
+    }
+
+    /// Moves the file `src_name` of `src` to `dst_name` in `dst`, replacing
+    /// it with a new empty file in the same step.
+    pub(crate) fn rotate_file(
+        src: &Arc<Self>,
+        src_name: &str,
+        dst: &Arc<Self>,
+        dst_name: &str,
+    ) -> VfsResult {
+        let fresh = FileNode::new(src.this.clone(), src.ctx.clone());
+        Self::move_node(src, src_name, dst, dst_name, true, Some(fresh))
+    }
+
+    /// Checks whether this directory is `node` itself or one of its
+    /// descendants.
+    fn is_descendant_of(&self, node: &VfsNodeRef) -> bool {
+        let mut cur = self.this.upgrade().map(|this| this as VfsNodeRef);
+        while let Some(dir) = cur {
+            if Arc::ptr_eq(&dir, node) {
+                return true;
+            }
+            cur = dir.parent();
+        }
+        false
+    }
+
+    /// Resolves `path` to the directory containing its last component and
+    /// the name of that component.
+    pub(crate) fn resolve_parent<'a>(
+        self: &Arc<Self>,
+        path: &'a str,
+    ) -> VfsResult<(Arc<Self>, &'a str)> {
+        let path = path.trim_end_matches('/');
+        let (dir, name) = match path.rfind('/') {
+            Some(n) => (as_dir(self.clone().lookup(&path[..n])?)?, &path[n + 1..]),
+            None => (self.clone(), path),
+        };
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(VfsError::InvalidInput);
+        }
+        Ok((dir, name))
+    }
+
+    /// Creates a symbolic link with the given name and target in this
+    /// directory.
+    ///
+    /// Fails with [`VfsError::InvalidInput`] if `target` is empty or contains
+    /// a NUL byte, and with [`VfsError::NameTooLong`] if it is longer than
+    /// the maximum set with
+    /// [`RamFileSystem::set_max_symlink_len`](crate::RamFileSystem::set_max_symlink_len).
+    pub(crate) fn create_symlink(&self, name: &str, target: &str) -> VfsResult {
+        self.ctx.check_symlink_target(target)?;
+        self.insert_symlink(name, SymlinkNode::new(target, self.ctx.clone()))
+    }
+
+    /// Creates a symbolic link whose target is generated by `generate` on
+    /// every access, e.g. to point to per-process state.
+    pub fn create_dynamic_symlink(
+        &self,
+        name: &str,
+        generate: impl Fn() -> String + Send + Sync + 'static,
+    ) -> VfsResult {
+        let link = SymlinkNode::new_dynamic(Arc::new(move || Ok(generate())), self.ctx.clone());
+        self.insert_symlink(name, link)
+    }
+
+    /// Creates a symbolic link like
+    /// [`create_dynamic_symlink`](Self::create_dynamic_symlink), but
+    /// generates the target only once and then returns it until
+    /// [`SymlinkNode::invalidate`] is called.
+    ///
+    /// The cached target may therefore be stale.
+    pub fn create_dynamic_symlink_cached(
+        &self,
+        name: &str,
+        generate: impl Fn() -> String + Send + Sync + 'static,
+    ) -> VfsResult {
+        let generate = Arc::new(move || Ok(generate()));
+        let link = SymlinkNode::new_dynamic_cached(generate, self.ctx.clone());
+        self.insert_symlink(name, link)
+    }
+
+    /// Creates a symbolic link like
+    /// [`create_dynamic_symlink`](Self::create_dynamic_symlink), whose
+    /// target is generated by a callback that may fail, e.g. because the
+    /// state it points to is temporarily unavailable.
+    ///
+    /// The error of a failed generation is returned instead of the target,
+    /// e.g. by [`readlink`](VfsNodeOps::readlink).
+    pub fn create_dynamic_symlink_fallible(
+        &self,
+        name: &str,
+        generate: impl Fn() -> VfsResult<String> + Send + Sync + 'static,
+    ) -> VfsResult {
+        let link = SymlinkNode::new_dynamic(Arc::new(generate), self.ctx.clone());
+        self.insert_symlink(name, link)
+    }
+
+    fn insert_symlink(&self, name: &str, link: Arc<SymlinkNode>) -> VfsResult {
+        self.check_writable()?;
+        self.check_child_type(VfsNodeType::SymLink)?;
+        let mut children = self.children.write();
+        if children.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        self.check_room(&children, 1)?;
+        children.insert(name.into(), link);
+        drop(children);
+        self.touch();
+        self.notify(|| FsEvent::Create(name.into()));
+        Ok(())
+    }
+
+    /// Creates a file with the given name and content in this directory.
+    pub(crate) fn create_file(&self, name: &str, content: Vec<u8>) -> VfsResult<Arc<FileNode>> {
+        self.check_writable()?;
+        self.check_child_type(VfsNodeType::File)?;
+        let mut children = self.children.write();
+        if children.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        self.check_room(&children, 1)?;
+        let file = FileNode::with_content(self.this.clone(), self.ctx.clone(), content)?;
+        children.insert(name.into(), file.clone());
+        drop(children);
+        self.touch();
+        self.notify(|| FsEvent::Create(name.into()));
+        Ok(file)
+    }
+
+    /// Resolves `path` to a directory, creating it and all its missing
+    /// ancestors if they do not exist.
+    pub(crate) fn create_dir_all(self: &Arc<Self>, path: &str) -> VfsResult<Arc<Self>> {
+        let mut cur = self.clone();
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let node = match cur.traverse_path(name) {
+                Err(VfsError::NotFound) if name != ".." => {
+                    match cur.create_node(name, VfsNodeType::Dir) {
+                        Ok(()) | Err(VfsError::AlreadyExists) => {}
+                        Err(err) => return Err(err),
+                    }
+                    cur.traverse_path(name)?
+                }
+                res => res?,
+            };
+            cur = as_dir(node)?;
+        }
+        Ok(cur)
+    }
+
+    /// Calls `f` with the path (relative to this directory) and the node of
+    /// every descendant of this directory, in depth-first order.
+    ///
+    /// A directory is visited before its entries, and entries are visited
+    /// in name order. Symbolic links are visited but not followed.
+    ///
+    /// The children lock is not held while `f` runs, so `f` may look up or
+    /// modify other nodes. Every directory is entered at most once, so the
+    /// walk terminates even if a cycle has been created with
+    /// [`add_node`](VfsNodeOps::add_node).
+    pub fn walk(&self, f: &mut dyn FnMut(&str, &VfsNodeRef)) {
+        let mut visited = BTreeSet::new();
+        visited.insert(self as *const Self as usize);
+        self.walk_inner("", &mut visited, f);
+    }
+
+    fn walk_inner(
+        &self,
+        prefix: &str,
+        visited: &mut BTreeSet<usize>,
+        f: &mut dyn FnMut(&str, &VfsNodeRef),
+    ) {
+        let children: Vec<_> = self
+            .children
+            .read()
+            .iter()
+            .map(|(name, node)| (name.clone(), node.clone()))
+            .collect();
+        for (name, node) in children {
+            let path = if prefix.is_empty() {
+                name
+            } else {
+                format!("{prefix}/{name}")
+            };
+            f(&path, &node);
+            if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+                if visited.insert(dir as *const Self as usize) {
+                    dir.walk_inner(&path, visited, f);
+                }
+            }
+        }
+    }
+
+    /// Returns the total size of the files under this directory, recursively.
+    ///
+    /// A file with several hard links in the subtree is only counted once.
+    /// Like [`walk`](Self::walk), no lock is held while descending.
+    pub fn subtree_bytes(&self) -> usize {
+        let mut files = BTreeSet::new();
+        let mut total = 0;
+        self.walk(&mut |_, node| {
+            if let Some(file) = node.as_any().downcast_ref::<FileNode>() {
+                if files.insert(file as *const FileNode as usize) {
+                    total += file.byte_len();
+                }
+            }
+        });
+        total
+    }
+
+    /// Sets the attributes like [`set_attr`](VfsNodeOps::set_attr), without
+    /// checking whether the directory is writable, to copy them to a new
+    /// directory.
+    pub(crate) fn copy_attr(&self, attr: &VfsNodeAttr) {
+        self.meta.set_attr(attr, self.ctx.now());
+    }
+
+    /// Copies the entries of this directory into the empty directory `dst`,
+    /// recursively.
+    ///
+    /// Files, directories and symbolic links are duplicated along with their
+    /// permissions and read-only marks, and nodes of other types are shared.
+    /// A node reachable through several entries, like a hard-linked file or
+    /// a directory in a cycle, is copied once and linked the same way in the
+    /// copy. File contents are shared until written. The copies are filled
+    /// without checking whether `dst` is writable.
+    pub(crate) fn copy_into(&self, dst: &Arc<Self>) -> VfsResult {
+        let mut copies = BTreeMap::new();
+        copies.insert(self as *const Self as usize, dst.clone() as VfsNodeRef);
+        self.copy_into_inner(dst, &mut copies)?;
+        dst.set_subtree_readonly(self.readonly.load(Ordering::Acquire));
+        Ok(())
+    }
+
+    fn copy_into_inner(
+        &self,
+        dst: &Arc<Self>,
+        copies: &mut BTreeMap<usize, VfsNodeRef>,
+    ) -> VfsResult {
+        let children: Vec<_> = self
+            .children
+            .read()
+            .iter()
+            .map(|(name, node)| (name.clone(), node.clone()))
+            .collect();
+        for (name, node) in children {
+            let key = Arc::as_ptr(&node) as *const () as usize;
+            let copy = match copies.get(&key) {
+                Some(copy) => {
+                    count_link(copy, true);
+                    copy.clone()
+                }
+                None => {
+                    let any = node.as_any();
+                    let copy: VfsNodeRef = if let Some(file) = any.downcast_ref::<FileNode>() {
+                        let copy = file.share_content(dst.this.clone(), dst.ctx.clone())?;
+                        copy.copy_attr(&file.get_attr()?);
+                        copy
+                    } else if let Some(dir) = any.downcast_ref::<DirNode>() {
+                        let copy = Self::new(Some(dst.this.clone()), dst.ctx.clone());
+                        copy.copy_attr(&dir.get_attr()?);
+                        copies.insert(key, copy.clone());
+                        dir.copy_into_inner(&copy, copies)?;
+                        copy.set_subtree_readonly(dir.readonly.load(Ordering::Acquire));
+                        copy
+                    } else if let Some(link) = any.downcast_ref::<SymlinkNode>() {
+                        link.duplicate(dst.ctx.clone()) // the permissions are fixed
+                    } else if let Some(fifo) = any.downcast_ref::<FifoNode>() {
+                        let copy = FifoNode::new(dst.this.clone(), dst.ctx.clone()); // bytes are not copied
+                        copy.copy_attr(&fifo.get_attr()?);
+                        copy
+                    } else {
+                        copies.insert(key, node.clone());
+                        dst.children.write().insert(name, node);
+                        continue;
+                    };
+                    copies.insert(key, copy.clone());
+                    copy
+                }
+            };
+            dst.children.write().insert(name, copy);
+        }
+        Ok(())
+    }
+
+    /// Removes the empty directories under this directory, bottom-up, and
+    /// returns how many were removed.
+    pub(crate) fn prune_empty_dirs(&self) -> VfsResult<usize> {
+        let mut visited = BTreeSet::new();
+        visited.insert(self as *const Self as usize);
+        self.prune_empty_dirs_inner(&mut visited)
+    }
+
+    fn prune_empty_dirs_inner(&self, visited: &mut BTreeSet<usize>) -> VfsResult<usize> {
+        let subdirs: Vec<_> = self
+            .children
+            .read()
+            .iter()
+            .filter(|(_, node)| node.as_any().is::<DirNode>())
+            .map(|(name, node)| (name.clone(), node.clone()))
+            .collect();
+        let mut count = 0;
+        for (name, node) in subdirs {
+            let dir = node.as_any().downcast_ref::<DirNode>().unwrap();
+            if !visited.insert(dir as *const Self as usize) {
+                continue;
+            }
+            count += dir.prune_empty_dirs_inner(visited)?;
+            self.check_writable()?;
+            let mut children = self.children.write();
+            // the directory may have been filled or replaced in the meantime
+            let unchanged = children
+                .get(&name)
+                .is_some_and(|cur| Arc::ptr_eq(cur, &node));
+            if unchanged && dir.is_empty() {
+                children.remove(&name);
+                drop(children);
+                count += 1;
+                self.touch();
+                self.notify(|| FsEvent::Remove(name));
+            }
+        }
+        Ok(count)
+    }
+
+    /// Returns the paths, relative to this directory, of the entries that
+    /// refer back to one of their own ancestor directories.
+    pub(crate) fn find_cycles(&self) -> Vec<String> {
+        let mut cycles = Vec::new();
+        let mut stack = vec![self as *const Self as usize];
+        self.find_cycles_inner("", &mut stack, &mut BTreeSet::new(), &mut cycles);
+        cycles
+    }
+
+    fn find_cycles_inner(
+        &self,
+        prefix: &str,
+        stack: &mut Vec<usize>,
+        done: &mut BTreeSet<usize>,
+        cycles: &mut Vec<String>,
+    ) {
+        let children: Vec<_> = self
+            .children
+            .read()
+            .iter()
+            .map(|(name, node)| (name.clone(), node.clone()))
+            .collect();
+        for (name, node) in children {
+            let Some(dir) = node.as_any().downcast_ref::<DirNode>() else {
+                continue;
+            };
+            let path = format!("{prefix}/{name}");
+            let addr = dir as *const Self as usize;
+            if stack.contains(&addr) {
+                cycles.push(path);
+            } else if !done.contains(&addr) {
+                stack.push(addr);
+                dir.find_cycles_inner(&path, stack, done, cycles);
+                stack.pop();
+                done.insert(addr);
+            }
+        }
+    }
+
+    /// Removes the entry `name` regardless of its type and content, and
+    /// returns it.
+    pub(crate) fn detach(&self, name: &str) -> VfsResult<VfsNodeRef> {
+        self.check_writable()?;
+        let node = self
+            .children
+            .write()
+            .remove(name)
+            .ok_or(VfsError::NotFound)?;
+        count_link(&node, false);
+        self.touch();
+        self.notify(|| FsEvent::Remove(name.into()));
+        Ok(node)
+    }
+
+    /// Removes all entries of this directory regardless of their type and
+    /// content, and returns how many were removed.
+    ///
+    /// The entries are not cleared, so nodes still referenced elsewhere keep
+    /// their content and descendants.
+    pub(crate) fn detach_all(&self) -> VfsResult<usize> {
+        self.check_writable()?;
+        let children = self.children.write().take();
+        children.values().for_each(|node| count_link(node, false));
+        self.touch();
+        let count = children.len();
+        for name in children.into_keys() {
+            self.notify(|| FsEvent::Remove(name));
+        }
+        Ok(count)
+    }
+
     /// Helper method to traverse path components (., .., or child names)
-    fn traverse_path(&self, name: &str) -> VfsResult<VfsNodeRef> {
+    pub(crate) fn traverse_path(&self, name: &str) -> VfsResult<VfsNodeRef> {
         match name {
             "" | "." => Ok(self.this.upgrade().ok_or(VfsError::NotFound)? as VfsNodeRef),
             ".." => self.parent().ok_or(VfsError::NotFound),
@@ -84,9 +1559,48 @@ impl DirNode {
     }
 }
 
+impl Drop for DirNode {
+    fn drop(&mut self) {
+        self.ctx.node_dropped(VfsNodeType::Dir);
+    }
+}
+
 impl VfsNodeOps for DirNode {
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_dir(4096, 0))
+        let subdirs = self
+            .children
+            .read()
+            .values()
+            .filter(|node| node.as_any().is::<DirNode>())
+            .count();
+        let mut attr = self.meta.attr(VfsNodeType::Dir, 4096);
+        // `.` and the entry in the parent, plus `..` of each subdirectory
+        attr.set_nlink(2 + subdirs as u64);
+        Ok(attr)
+    }
+
+    fn set_attr(&self, attr: &VfsNodeAttr) -> VfsResult {
+        self.check_writable()?;
+        self.copy_attr(attr);
+        Ok(())
+    }
+
+    fn get_xattr(&self, name: &str, buf: &mut [u8]) -> VfsResult<usize> {
+        self.meta.get_xattr(name, buf)
+    }
+
+    fn set_xattr(&self, name: &str, value: &[u8]) -> VfsResult {
+        self.check_writable()?;
+        self.meta.set_xattr(name, value, self.ctx.now())
+    }
+
+    fn list_xattr(&self) -> VfsResult<Vec<String>> {
+        Ok(self.meta.list_xattr())
+    }
+
+    fn remove_xattr(&self, name: &str) -> VfsResult {
+        self.check_writable()?;
+        self.meta.remove_xattr(name, self.ctx.now())
     }
 
     fn parent(&self) -> Option<VfsNodeRef> {
@@ -94,6 +1608,7 @@ impl VfsNodeOps for DirNode {
     }
 
     fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        self.check_access(AccessMode::Lookup)?;
         let (name, rest) = split_path(path);
         let node = self.traverse_path(name)?;
 
@@ -104,7 +1619,16 @@ impl VfsNodeOps for DirNode {
         }
     }
 
+    /// Lists the entries from index `start_idx` on, in name order, after `.`
+    /// and `..`.
+    ///
+    /// Entries created or removed between two calls shift the indices, so
+    /// an entry can be skipped or listed twice. Use
+    /// [`DirNode::read_dir_after`] to page through a directory that may
+    /// change.
     fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        self.check_access(AccessMode::Read)?;
+        self.meta.touch_accessed(self.ctx.now());
         let children = self.children.read();
         let mut children = children.iter().skip(start_idx.max(2) - 2);
         for (i, ent) in dirents.iter_mut().enumerate() {
@@ -113,7 +1637,7 @@ impl VfsNodeOps for DirNode {
                 1 => *ent = VfsDirEntry::new("..", VfsNodeType::Dir),
                 _ => {
                     if let Some((name, node)) = children.next() {
-                        *ent = VfsDirEntry::new(name, node.get_attr().unwrap().file_type());
+                        *ent = VfsDirEntry::new(name, node_type(node)?);
                     } else {
                         return Ok(i);
                     }
@@ -126,7 +1650,7 @@ impl VfsNodeOps for DirNode {
     fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
         let (name, rest) = split_path(path);
         if let Some(rest) = rest {
-            self.traverse_path(name)?.create(rest, ty)
+            self.descend_for_create(name)?.create(rest, ty)
         } else if name.is_empty() || name == "." || name == ".." {
             Ok(()) // already exists
         } else {
@@ -145,15 +1669,99 @@ impl VfsNodeOps for DirNode {
         }
     }
 
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        let (src_dir, src_name) = this.resolve_parent(src_path)?;
+        let (dst_dir, dst_name) = this.resolve_parent(dst_path)?;
+        Self::move_node(&src_dir, src_name, &dst_dir, dst_name, true, None)
+    }
+
+    fn symlink(&self, target: &str, path: &str) -> VfsResult {
+        match split_path(path.trim_end_matches('/')) {
+            (name, Some(rest)) => as_dir(self.descend_for_create(name)?)?.symlink(target, rest),
+            (name, None) if name.is_empty() || name == "." || name == ".." => {
+                Err(VfsError::InvalidInput)
+            }
+            (name, None) => self.create_symlink(name, target),
+        }
+    }
+
+    /// Adds an entry at `dst_path` referring to the node at `src_path`, like
+    /// `link(2)`, both relative to this directory.
+    ///
+    /// See [`DirNode::link_node`] for the errors.
+    fn link(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        let target = this.clone().lookup(src_path)?;
+        let (dir, name) = this.resolve_parent(dst_path)?;
+        dir.link_exclusive(name, target)
+    }
+
+    fn readlink(&self, path: &str, buf: &mut [u8]) -> VfsResult<usize> {
+        let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        this.lookup(path)?.readlink("", buf)
+    }
+
+    /// Adds `node` as the entry `name`, replacing any existing one.
+    ///
+    /// Fails with [`VfsError::InvalidInput`] if `node` is this directory or
+    /// one of its ancestors, which would make the tree cyclic.
     fn add_node(&self, name: &'static str, node: VfsNodeRef) -> VfsResult {
-        self.children.write().insert(name.to_string(), node);
+        self.check_writable()?;
+        let ty = node.get_attr()?.file_type();
+        if ty.is_dir() && self.is_descendant_of(&node) {
+            return Err(VfsError::InvalidInput);
+        }
+        self.check_child_type(ty)?;
+        let mut children = self.children.write();
+        self.check_room(&children, !children.contains_key(name) as usize)?;
+        count_link(&node, true);
+        if let Some(old) = children.insert(name.to_string(), node.clone()) {
+            count_link(&old, false);
+        }
+        drop(children);
+        if let Some(gen) = node.as_any().downcast_ref::<GenDirNode>() {
+            gen.set_parent(self.this.clone()); // other nodes keep their parent
+        }
+        self.touch();
+        self.notify(|| FsEvent::Create(name.into()));
         Ok(())
     }
 
     axfs_vfs::impl_vfs_dir_default! {}
 }
 
-fn split_path(path: &str) -> (&str, Option<&str>) {
+/// Updates the link count of `node` after a directory entry referring to it
+/// was added or removed.
+fn count_link(node: &VfsNodeRef, added: bool) {
+    if let Some(file) = node.as_any().downcast_ref::<FileNode>() {
+        file.count_link(added);
+    }
+}
+
+/// Returns the type of `node`.
+///
+/// Symbolic links are recognized without reading their attributes, which
+/// fails for a dynamic link whose target cannot be generated.
+fn node_type(node: &VfsNodeRef) -> VfsResult<VfsNodeType> {
+    match node.is_symlink() {
+        true => Ok(VfsNodeType::SymLink),
+        false => Ok(node.get_attr()?.file_type()),
+    }
+}
+
+/// Converts `node` to a [`DirNode`] of this crate.
+///
+/// Fails with [`VfsError::NotADirectory`] if it is not a directory, or with
+/// [`VfsError::CrossesDevices`] if it is a directory of another filesystem.
+fn as_dir(node: VfsNodeRef) -> VfsResult<Arc<DirNode>> {
+    if !node.get_attr()?.is_dir() {
+        return Err(VfsError::NotADirectory);
+    }
+    downcast_node(node).ok_or(VfsError::CrossesDevices)
+}
+
+pub(crate) fn split_path(path: &str) -> (&str, Option<&str>) {
     let trimmed_path = path.trim_start_matches('/');
     trimmed_path.find('/').map_or((trimmed_path, None), |n| {
         (&trimmed_path[..n], Some(&trimmed_path[n + 1..]))
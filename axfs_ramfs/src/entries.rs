@@ -0,0 +1,126 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use axfs_vfs::VfsNodeRef;
+use core::ops::{Bound, Deref};
+use hashbrown::HashMap;
+
+/// The entries of a directory, sorted by name.
+///
+/// The entries are also indexed by a hash map, so looking up a name does not
+/// have to compare it with the names along a path of the tree, which is
+/// slow for large directories with long common prefixes.
+///
+/// Each name is stored in both maps, so an entry takes roughly twice the
+/// memory it would in the `BTreeMap` alone. Case folding adds two more
+/// copies of each name, the lowercase key and the original.
+///
+/// With case folding enabled, names that differ only in case refer to the
+/// same entry, which keeps the casing it was inserted with. Read-only access
+/// to the underlying map goes through [`Deref`], and only sees the original
+/// names.
+#[derive(Clone)]
+pub(crate) struct Entries {
+    map: BTreeMap<String, VfsNodeRef>,
+    /// The same entries as `map`, for lookups.
+    index: HashMap<String, VfsNodeRef>,
+    /// Maps the lowercase names to the keys of `map`, if case folding is
+    /// enabled.
+    folded: Option<HashMap<String, String>>,
+}
+
+impl Entries {
+    pub fn new(fold_case: bool) -> Self {
+        Self {
+            map: BTreeMap::new(),
+            index: HashMap::new(),
+            folded: fold_case.then(HashMap::new),
+        }
+    }
+
+    /// Returns the key of the entry `name` refers to.
+    fn key<'a>(&'a self, name: &'a str) -> &'a str {
+        match &self.folded {
+            Some(folded) => folded
+                .get(&name.to_lowercase())
+                .map_or(name, String::as_str),
+            None => name,
+        }
+    }
+
+    /// Checks whether `a` and `b` refer to the same entry.
+    pub fn same_entry(&self, a: &str, b: &str) -> bool {
+        self.key(a) == self.key(b)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&VfsNodeRef> {
+        self.index.get(self.key(name))
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.index.contains_key(self.key(name))
+    }
+
+    /// Inserts `node` as `name`, replacing the entry `name` refers to, and
+    /// returns the replaced node.
+    pub fn insert(&mut self, name: String, node: VfsNodeRef) -> Option<VfsNodeRef> {
+        let old = self.remove(&name);
+        if let Some(folded) = &mut self.folded {
+            folded.insert(name.to_lowercase(), name.clone());
+        }
+        self.index.insert(name.clone(), node.clone());
+        self.map.insert(name, node);
+        old
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<VfsNodeRef> {
+        match &mut self.folded {
+            Some(folded) => {
+                let key = folded.remove(&name.to_lowercase())?;
+                self.index.remove(&key);
+                self.map.remove(&key)
+            }
+            None => {
+                self.index.remove(name);
+                self.map.remove(name)
+            }
+        }
+    }
+
+    /// Returns the entries whose names start with `prefix`, in order.
+    ///
+    /// With case folding enabled, the prefix matches regardless of case.
+    pub fn with_prefix(&self, prefix: &str) -> Vec<(&String, &VfsNodeRef)> {
+        match &self.folded {
+            Some(_) => {
+                let prefix = prefix.to_lowercase();
+                self.map
+                    .iter()
+                    .filter(|(name, _)| name.to_lowercase().starts_with(&prefix))
+                    .collect()
+            }
+            None => self
+                .map
+                .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+                .take_while(|(name, _)| name.starts_with(prefix))
+                .collect(),
+        }
+    }
+
+    /// Removes and returns all entries, keeping the case folding mode.
+    pub fn take(&mut self) -> BTreeMap<String, VfsNodeRef> {
+        if let Some(folded) = &mut self.folded {
+            folded.clear();
+        }
+        self.index.clear();
+        core::mem::take(&mut self.map)
+    }
+}
+
+impl Deref for Entries {
+    type Target = BTreeMap<String, VfsNodeRef>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.map
+    }
+}
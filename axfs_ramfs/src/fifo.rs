@@ -0,0 +1,126 @@
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use axfs_vfs::{impl_vfs_non_dir_default, VfsError, VfsNodeAttr, VfsNodeOps, VfsResult};
+use axfs_vfs::{VfsNodePerm, VfsNodeType};
+use spin::RwLock;
+
+use crate::context::FsContext;
+use crate::dir::DirNode;
+use crate::meta::NodeMeta;
+
+/// The number of bytes a FIFO buffers, as the default pipe capacity in Linux.
+const FIFO_CAPACITY: usize = 65536;
+
+/// The FIFO (named pipe) node in the RAM filesystem.
+///
+/// It implements [`axfs_vfs::VfsNodeOps`]. Bytes written with
+/// [`write_at`](VfsNodeOps::write_at) are buffered, up to a fixed capacity,
+/// until drained in the same order by [`read_at`](VfsNodeOps::read_at); the
+/// offsets are ignored.
+///
+/// There is no way to wait for the other end without a scheduler, so the
+/// node never blocks: writing to a full FIFO and reading from an empty one
+/// fail with [`VfsError::WouldBlock`], like a pipe opened with `O_NONBLOCK`,
+/// and the caller is expected to retry. The buffered bytes are not counted
+/// in the memory usage of the filesystem.
+pub struct FifoNode {
+    ctx: Arc<FsContext>,
+    meta: NodeMeta,
+    dir: RwLock<Weak<DirNode>>,
+    buf: RwLock<VecDeque<u8>>,
+}
+
+impl FifoNode {
+    pub(super) fn new(dir: Weak<DirNode>, ctx: Arc<FsContext>) -> Arc<Self> {
+        Arc::new(Self {
+            meta: NodeMeta::new(
+                ctx.alloc_ino(),
+                ctx.mask_file_perm(VfsNodePerm::default_file()),
+                ctx.now(),
+            ),
+            ctx,
+            dir: RwLock::new(dir),
+            buf: RwLock::new(VecDeque::new()),
+        })
+    }
+
+    pub(super) fn set_dir(&self, dir: Weak<DirNode>) {
+        *self.dir.write() = dir;
+    }
+
+    /// Sets the attributes like [`set_attr`](VfsNodeOps::set_attr), without
+    /// checking whether the FIFO is writable, to copy them to a new FIFO.
+    pub(crate) fn copy_attr(&self, attr: &VfsNodeAttr) {
+        let mut attr = *attr;
+        attr.set_perm(self.ctx.mask_file_perm(attr.perm()));
+        self.meta.set_attr(&attr, self.ctx.now());
+    }
+
+    /// Fails if the directory containing this FIFO is in a read-only subtree.
+    fn check_writable(&self) -> VfsResult {
+        if self.ctx.readonly() {
+            return Err(VfsError::ReadOnlyFilesystem);
+        }
+        match self.dir.read().upgrade() {
+            Some(dir) => dir.check_writable(),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the number of bytes written but not read yet.
+    pub fn available(&self) -> usize {
+        self.buf.read().len()
+    }
+}
+
+impl VfsNodeOps for FifoNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(self.meta.attr(VfsNodeType::Fifo, 0))
+    }
+
+    fn set_attr(&self, attr: &VfsNodeAttr) -> VfsResult {
+        self.check_writable()?;
+        self.copy_attr(attr);
+        Ok(())
+    }
+
+    /// Drains up to `buf.len()` bytes in the order they were written.
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut fifo = self.buf.write();
+        if fifo.is_empty() {
+            return Err(VfsError::WouldBlock);
+        }
+        let len = buf.len().min(fifo.len());
+        for (dst, src) in buf.iter_mut().zip(fifo.drain(..len)) {
+            *dst = src;
+        }
+        self.meta.touch_accessed(self.ctx.now());
+        Ok(len)
+    }
+
+    /// Appends as much of `buf` as fits, and returns the number of bytes
+    /// written.
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut fifo = self.buf.write();
+        let len = buf.len().min(FIFO_CAPACITY - fifo.len());
+        if len == 0 {
+            return Err(VfsError::WouldBlock);
+        }
+        fifo.extend(&buf[..len]);
+        self.meta.touch_modified(self.ctx.now());
+        Ok(len)
+    }
+
+    /// FIFOs have no size, so truncating them has no effect, as in Linux.
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    impl_vfs_non_dir_default! {}
+}
@@ -1,54 +1,511 @@
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
-use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsResult};
+use axfs_vfs::{impl_vfs_non_dir_default, VfsError, VfsNodeAttr, VfsNodeOps, VfsResult};
+use axfs_vfs::{VfsNodePerm, VfsNodeRef, VfsNodeType};
+use core::any::Any;
+use core::sync::atomic::{AtomicU64, Ordering};
 use spin::RwLock;
 
+use crate::context::{AccessMode, FsContext};
+use crate::dir::DirNode;
+use crate::meta::NodeMeta;
+
 /// The file node in the RAM filesystem.
 ///
 /// It implements [`axfs_vfs::VfsNodeOps`].
 pub struct FileNode {
-    content: RwLock<Vec<u8>>,
+    this: Weak<FileNode>,
+    ctx: Arc<FsContext>,
+    dir: RwLock<Weak<DirNode>>,
+    nlink: AtomicU64,
+    meta: NodeMeta,
+    /// Shared with the clones made by [`clone_cow`](Self::clone_cow) until
+    /// either side writes.
+    content: RwLock<Arc<Vec<u8>>>,
 }
 
 impl FileNode {
-    pub(super) const fn new() -> Self {
-        Self {
-            content: RwLock::new(Vec::new()),
+    pub(super) fn new(dir: Weak<DirNode>, ctx: Arc<FsContext>) -> Arc<Self> {
+        Self::build(dir, ctx, Arc::default())
+    }
+
+    /// Creates a file holding `content`, failing with
+    /// [`VfsError::StorageFull`] if it does not fit in the capacity of the
+    /// filesystem.
+    pub(super) fn with_content(
+        dir: Weak<DirNode>,
+        ctx: Arc<FsContext>,
+        content: Vec<u8>,
+    ) -> VfsResult<Arc<Self>> {
+        ctx.account(0, content.len())?;
+        Ok(Self::build(dir, ctx, Arc::new(content)))
+    }
+
+    /// Creates a file with the content of this one in the directory `dir`
+    /// of the filesystem of `ctx`, sharing the buffer until either file is
+    /// written.
+    pub(super) fn share_content(
+        &self,
+        dir: Weak<DirNode>,
+        ctx: Arc<FsContext>,
+    ) -> VfsResult<Arc<Self>> {
+        let content = self.content();
+        ctx.account(0, content.len())?;
+        Ok(Self::build(dir, ctx, content))
+    }
+
+    /// Creates the node, with `content` already accounted for.
+    fn build(dir: Weak<DirNode>, ctx: Arc<FsContext>, content: Arc<Vec<u8>>) -> Arc<Self> {
+        ctx.node_created(VfsNodeType::File);
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            meta: NodeMeta::new(
+                ctx.alloc_ino(),
+                ctx.mask_file_perm(VfsNodePerm::default_file()),
+                ctx.now(),
+            ),
+            ctx,
+            dir: RwLock::new(dir),
+            nlink: AtomicU64::new(1),
+            content: RwLock::new(content),
+        })
+    }
+
+    pub(super) fn set_dir(&self, dir: Weak<DirNode>) {
+        *self.dir.write() = dir;
+    }
+
+    /// Returns the directory the file was created in or last moved to.
+    pub(super) fn dir(&self) -> Option<Arc<DirNode>> {
+        self.dir.read().upgrade()
+    }
+
+    /// Updates the link count after a directory entry referring to this file
+    /// was added or removed.
+    pub(super) fn count_link(&self, added: bool) {
+        if added {
+            self.nlink.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.nlink.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Sets the attributes like [`set_attr`](VfsNodeOps::set_attr), without
+    /// checking whether the file is writable, to copy them to a new file.
+    pub(crate) fn copy_attr(&self, attr: &VfsNodeAttr) {
+        let mut attr = *attr;
+        attr.set_perm(self.ctx.mask_file_perm(attr.perm()));
+        self.meta.set_attr(&attr, self.ctx.now());
+    }
+
+    /// Fails if the directory containing this file is in a read-only subtree.
+    fn check_writable(&self) -> VfsResult {
+        if self.ctx.readonly() {
+            return Err(VfsError::ReadOnlyFilesystem);
+        }
+        match self.dir.read().upgrade() {
+            Some(dir) => dir.check_writable(),
+            None => Ok(()),
+        }
+    }
+
+    fn check_access(&self, mode: AccessMode) -> VfsResult {
+        self.ctx
+            .check_access(|| self.this.upgrade().map(|n| n as VfsNodeRef), mode)
+    }
+
+    /// Returns the metadata version of the file.
+    ///
+    /// The version is bumped on every metadata change, including content
+    /// modifications and extended attributes, except access time updates.
+    pub fn version(&self) -> u64 {
+        self.meta.version()
+    }
+
+    /// Attaches arbitrary user data to the file, replacing any previous one.
+    pub fn set_user_data(&self, data: Arc<dyn Any + Send + Sync>) {
+        self.meta.set_user_data(data)
+    }
+
+    /// Returns the user data attached to the file, if any.
+    ///
+    /// Use [`Arc::downcast`] to get the concrete type back.
+    pub fn user_data(&self) -> Option<Arc<dyn Any + Send + Sync>> {
+        self.meta.user_data()
+    }
+
+    /// Applies the metadata in `attr` only if the current metadata version is
+    /// `expected_version`, and returns the new version.
+    ///
+    /// Returns [`VfsError::ResourceBusy`] if the version does not match, i.e.
+    /// the metadata has been changed concurrently.
+    pub fn compare_and_set_attr(&self, expected_version: u64, attr: VfsNodeAttr) -> VfsResult<u64> {
+        self.check_writable()?;
+        let mut attr = attr;
+        attr.set_perm(self.ctx.mask_file_perm(attr.perm()));
+        self.meta
+            .compare_and_set_attr(expected_version, &attr, self.ctx.now())
+    }
+
+    /// Creates a new file with the same content and permissions, without
+    /// copying the content.
+    ///
+    /// Both files share the same buffer until either of them is written,
+    /// which then copies it, so a large file that is only read is never
+    /// duplicated. The new file is not in any directory; add it to one with
+    /// [`DirNode::link_node`]. The content counts in the
+    /// [memory usage](crate::RamFileSystem::memory_usage) of both files,
+    /// even while it is shared.
+    pub fn clone_cow(&self) -> VfsResult<Arc<FileNode>> {
+        let content = self.content.read().clone();
+        self.ctx.account(0, content.len())?;
+        let file = Self::build(Weak::new(), self.ctx.clone(), content);
+        file.meta
+            .set_attr(&self.meta.attr(VfsNodeType::File, 0), self.ctx.now());
+        file.nlink.store(0, Ordering::Relaxed); // not linked anywhere yet
+        Ok(file)
+    }
+
+    /// Changes the owner of the file, like `chown`.
+    ///
+    /// A `None` user or group ID is left unchanged.
+    pub fn chown(&self, uid: Option<u32>, gid: Option<u32>) -> VfsResult {
+        self.check_writable()?;
+        self.meta.chown(uid, gid, self.ctx.now());
+        Ok(())
+    }
+
+    /// Replaces the whole content of the file with `data`.
+    ///
+    /// The new content is swapped in under a single write lock, so concurrent
+    /// readers see either the old or the new content, but never an empty or
+    /// partially written file.
+    pub fn replace_contents(&self, data: &[u8]) -> VfsResult {
+        self.check_writable()?;
+        self.check_access(AccessMode::Write)?;
+        let data = data.to_vec();
+        let mut content = self.content.write();
+        self.ctx.account(content.len(), data.len())?;
+        *content = Arc::new(data);
+        self.meta.touch_modified(self.ctx.now());
+        Ok(())
+    }
+
+    /// Moves the content out of the file, leaving it empty.
+    ///
+    /// Unlike reading and truncating, the bytes are not copied.
+    pub fn take_contents(&self) -> VfsResult<Vec<u8>> {
+        self.check_writable()?;
+        self.check_access(AccessMode::Write)?;
+        let mut content = self.content.write();
+        self.ctx.release(content.len());
+        let data = core::mem::take(&mut *content);
+        let data = Arc::try_unwrap(data).unwrap_or_else(|shared| (*shared).clone());
+        self.meta.touch_modified(self.ctx.now());
+        Ok(data)
+    }
+
+    /// Moves `data` into the file as its new content, without copying it.
+    ///
+    /// This is the counterpart of [`take_contents`](Self::take_contents).
+    pub fn give_contents(&self, data: Vec<u8>) -> VfsResult {
+        self.check_writable()?;
+        self.check_access(AccessMode::Write)?;
+        let mut content = self.content.write();
+        self.ctx.account(content.len(), data.len())?;
+        *content = Arc::new(data);
+        self.meta.touch_modified(self.ctx.now());
+        Ok(())
+    }
+
+    /// Reserves capacity for at least `additional` more bytes of content, so
+    /// a file whose final size is known can be written in small chunks
+    /// without reallocating.
+    ///
+    /// The size of the file does not change, and the reserved capacity is not
+    /// counted in the memory usage. Fails with [`VfsError::StorageFull`] if
+    /// the memory cannot be allocated.
+    pub fn reserve(&self, additional: usize) -> VfsResult {
+        self.check_writable()?;
+        let mut content = self.content.write();
+        Arc::make_mut(&mut content)
+            .try_reserve(additional)
+            .map_err(|_| VfsError::StorageFull)
+    }
+
+    /// Writes `buf` at the end of the file, and returns the number of bytes
+    /// written and the new size of the file.
+    ///
+    /// The end of the file is found under the same write lock as the write,
+    /// so concurrent appends never overwrite each other.
+    pub fn append(&self, buf: &[u8]) -> VfsResult<(usize, u64)> {
+        self.check_writable()?;
+        self.check_access(AccessMode::Write)?;
+        let mut content = self.content.write();
+        self.ctx.account(content.len(), content.len() + buf.len())?;
+        let content = Arc::make_mut(&mut content);
+        content.extend_from_slice(buf);
+        self.meta.touch_modified(self.ctx.now());
+        Ok((buf.len(), content.len() as u64))
+    }
+
+    /// Atomically adds `delta` to the little-endian `u64` stored in the first
+    /// 8 bytes of the file, and returns the previous value.
+    ///
+    /// Missing bytes (e.g. in an empty file) are treated as zero and
+    /// created. The addition wraps around on overflow.
+    pub fn fetch_add_le_u64(&self, delta: u64) -> VfsResult<u64> {
+        self.check_writable()?;
+        self.check_access(AccessMode::Write)?;
+        let mut content = self.content.write();
+        if content.len() < 8 {
+            self.ctx.account(content.len(), 8)?;
         }
+        let content = Arc::make_mut(&mut content);
+        content.resize(content.len().max(8), 0);
+        let old = u64::from_le_bytes(content[..8].try_into().unwrap());
+        content[..8].copy_from_slice(&old.wrapping_add(delta).to_le_bytes());
+        self.meta.touch_modified(self.ctx.now());
+        Ok(old)
+    }
+
+    /// Copies up to `len` bytes at `src_off` in this file to `dst_off` in
+    /// `dst`, and returns the number of bytes copied.
+    ///
+    /// The bytes are copied directly between the contents, without an
+    /// intermediate buffer. `len` is clamped at the end of this file, and
+    /// `dst` is extended with zeros if `dst_off` is past its end. `dst` may
+    /// be this file, in which case the ranges may overlap.
+    pub fn copy_range(
+        &self,
+        dst: &FileNode,
+        src_off: u64,
+        dst_off: u64,
+        len: usize,
+    ) -> VfsResult<usize> {
+        dst.check_writable()?;
+        self.check_access(AccessMode::Read)?;
+        dst.check_access(AccessMode::Write)?;
+        let dst_off = usize::try_from(dst_off).map_err(|_| VfsError::InvalidInput)?;
+        if core::ptr::eq(self, dst) {
+            let mut content = self.content.write();
+            let (start, end) = clamp_range(content.len(), src_off, len);
+            dst.write_with(&mut content, dst_off, end - start, |content, dst| {
+                content.copy_within(start..end, dst)
+            })?;
+            return Ok(end - start);
+        }
+        // Lock in address order, so opposite copies between two files
+        // cannot deadlock.
+        let (src, mut content) = match (self as *const Self) < (dst as *const Self) {
+            true => {
+                let src = self.content.read();
+                (src, dst.content.write())
+            }
+            false => {
+                let content = dst.content.write();
+                (self.content.read(), content)
+            }
+        };
+        let (start, end) = clamp_range(src.len(), src_off, len);
+        dst.write_with(&mut content, dst_off, end - start, |content, dst| {
+            content[dst..dst + end - start].copy_from_slice(&src[start..end])
+        })?;
+        self.meta.touch_accessed(self.ctx.now());
+        Ok(end - start)
+    }
+
+    /// Makes room for `len` bytes at `offset` in `content`, the locked
+    /// content of this file, and calls `f` to fill them in.
+    ///
+    /// If the room cannot be made, either because the capacity of the
+    /// filesystem would be exceeded or the memory cannot be allocated, the
+    /// content is left unchanged and [`VfsError::StorageFull`] is returned.
+    fn write_with(
+        &self,
+        content: &mut Arc<Vec<u8>>,
+        offset: usize,
+        len: usize,
+        f: impl FnOnce(&mut [u8], usize),
+    ) -> VfsResult {
+        if len == 0 {
+            return Ok(());
+        }
+        let end = offset.checked_add(len).ok_or(VfsError::InvalidInput)?;
+        if end > content.len() {
+            self.ctx.account(content.len(), end)?;
+        }
+        let content = Arc::make_mut(content);
+        if end > content.len() {
+            if content.try_reserve(end - content.len()).is_err() {
+                self.ctx.release(end - content.len());
+                return Err(VfsError::StorageFull);
+            }
+            content.resize(end, 0);
+        }
+        f(content, offset);
+        self.meta.touch_modified(self.ctx.now());
+        Ok(())
+    }
+
+    /// Returns the size of the content in bytes.
+    pub fn byte_len(&self) -> usize {
+        self.content.read().len()
+    }
+
+    /// Returns the number of bytes allocated for the content, which may be
+    /// more than its size, e.g. after [`reserve`](Self::reserve).
+    ///
+    /// The allocation is shared with the clones made by
+    /// [`clone_cow`](Self::clone_cow) until either side writes.
+    pub fn capacity(&self) -> usize {
+        self.content.read().capacity()
+    }
+
+    /// Returns the whole content of the file as a string.
+    ///
+    /// Returns [`VfsError::InvalidData`] if the content is not valid UTF-8.
+    pub fn read_to_string(&self) -> VfsResult<String> {
+        self.check_access(AccessMode::Read)?;
+        let content = self.content.read();
+        let text = core::str::from_utf8(&content).map_err(|_| VfsError::InvalidData)?;
+        self.meta.touch_accessed(self.ctx.now());
+        Ok(text.into())
+    }
+
+    /// Calls `f` with the content in `[offset, offset + len)`, clamped at the
+    /// end of the file, without copying it.
+    ///
+    /// The read lock is held while `f` runs, so `f` must not write to this
+    /// file. Returns [`VfsError::InvalidInput`] if `offset` is past the end of
+    /// the file.
+    pub fn with_range<R>(
+        &self,
+        offset: u64,
+        len: usize,
+        f: impl FnOnce(&[u8]) -> R,
+    ) -> VfsResult<R> {
+        self.check_access(AccessMode::Read)?;
+        let content = self.content.read();
+        if offset > content.len() as u64 {
+            return Err(VfsError::InvalidInput);
+        }
+        let start = offset as usize;
+        let end = content.len().min(start.saturating_add(len));
+        self.meta.touch_accessed(self.ctx.now());
+        Ok(f(&content[start..end]))
+    }
+
+    /// Calls `f` with the whole content of the file, without copying it.
+    ///
+    /// The read lock is held while `f` runs, so `f` must not write to this
+    /// file, or it deadlocks.
+    pub fn with_bytes<R>(&self, f: impl FnOnce(&[u8]) -> R) -> VfsResult<R> {
+        self.check_access(AccessMode::Read)?;
+        let content = self.content.read();
+        self.meta.touch_accessed(self.ctx.now());
+        Ok(f(&content))
+    }
+
+    /// Returns the content of the file without checking access or updating
+    /// the access time, to copy it along with the rest of the tree.
+    ///
+    /// The content is shared until either side writes to it.
+    pub(crate) fn content(&self) -> Arc<Vec<u8>> {
+        self.content.read().clone()
+    }
+}
+
+/// Returns the part of `[offset, offset + len)` below `size`.
+fn clamp_range(size: usize, offset: u64, len: usize) -> (usize, usize) {
+    let start = size.min(offset.try_into().unwrap_or(usize::MAX));
+    (start, size.min(start.saturating_add(len)))
+}
+
+impl Drop for FileNode {
+    fn drop(&mut self) {
+        self.ctx.release(self.content.get_mut().len());
+        self.ctx.node_dropped(VfsNodeType::File);
     }
 }
 
 impl VfsNodeOps for FileNode {
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_file(self.content.read().len() as _, 0))
+        let mut attr = self
+            .meta
+            .attr(VfsNodeType::File, self.content.read().len() as _);
+        attr.set_nlink(self.nlink.load(Ordering::Relaxed));
+        Ok(attr)
+    }
+
+    fn set_attr(&self, attr: &VfsNodeAttr) -> VfsResult {
+        self.check_writable()?;
+        self.copy_attr(attr);
+        Ok(())
+    }
+
+    fn get_xattr(&self, name: &str, buf: &mut [u8]) -> VfsResult<usize> {
+        self.meta.get_xattr(name, buf)
+    }
+
+    fn set_xattr(&self, name: &str, value: &[u8]) -> VfsResult {
+        self.check_writable()?;
+        self.meta.set_xattr(name, value, self.ctx.now())
+    }
+
+    fn list_xattr(&self) -> VfsResult<Vec<String>> {
+        Ok(self.meta.list_xattr())
+    }
+
+    fn remove_xattr(&self, name: &str) -> VfsResult {
+        self.check_writable()?;
+        self.meta.remove_xattr(name, self.ctx.now())
     }
 
     fn truncate(&self, size: u64) -> VfsResult {
+        self.check_writable()?;
+        self.check_access(AccessMode::Write)?;
+        let size = usize::try_from(size).map_err(|_| VfsError::InvalidInput)?;
         let mut content = self.content.write();
-        if size < content.len() as u64 {
-            content.truncate(size as _);
+        self.ctx.account(content.len(), size)?;
+        let content = Arc::make_mut(&mut content);
+        if size < content.len() {
+            content.truncate(size);
+            content.shrink_to_fit();
         } else {
-            content.resize(size as _, 0);
+            content.resize(size, 0); // the new tail reads back as zeros
         }
+        self.meta.touch_modified(self.ctx.now());
         Ok(())
     }
 
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        self.check_access(AccessMode::Read)?;
         let content = self.content.read();
-        let start = content.len().min(offset as usize);
-        let end = content.len().min(offset as usize + buf.len());
+        let start = content.len().min(offset.try_into().unwrap_or(usize::MAX));
+        let end = content.len().min(start.saturating_add(buf.len()));
         let src = &content[start..end];
         buf[..src.len()].copy_from_slice(src);
+        self.meta.touch_accessed(self.ctx.now());
         Ok(src.len())
     }
 
+    /// Writes all of `buf` at `offset`, extending the file with zeros if
+    /// `offset` is past its end, and returns `buf.len()`.
+    ///
+    /// A write is never partial: if the file cannot be extended to hold all
+    /// of `buf`, because the capacity of the filesystem would be exceeded or
+    /// the memory cannot be allocated, [`VfsError::StorageFull`] is returned
+    /// and the file is left unchanged.
     fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
-        let offset = offset as usize;
+        self.check_writable()?;
+        self.check_access(AccessMode::Write)?;
+        let offset = usize::try_from(offset).map_err(|_| VfsError::InvalidInput)?;
         let mut content = self.content.write();
-        if offset + buf.len() > content.len() {
-            content.resize(offset + buf.len(), 0);
-        }
-        let dst = &mut content[offset..offset + buf.len()];
-        dst.copy_from_slice(&buf[..dst.len()]);
+        self.write_with(&mut content, offset, buf.len(), |content, offset| {
+            content[offset..offset + buf.len()].copy_from_slice(buf)
+        })?;
         Ok(buf.len())
     }
 
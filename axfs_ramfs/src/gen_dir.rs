@@ -0,0 +1,127 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsError, VfsResult};
+use spin::RwLock;
+
+use crate::dir::{split_path, DirNode};
+
+type ListFn = dyn Fn() -> Vec<(String, VfsNodeType)> + Send + Sync;
+type ResolveFn = dyn Fn(&str) -> Option<VfsNodeRef> + Send + Sync;
+
+/// A directory node whose entries are generated on demand.
+///
+/// Listing the directory calls the `list` callback, and looking up a name
+/// calls the `resolve` callback, so the entry set can change between calls
+/// (e.g. a `/proc`-like directory listing live tasks). Nodes cannot be
+/// created in or removed from it.
+///
+/// Its `..` entry refers to the directory it was last added to.
+///
+/// It implements [`axfs_vfs::VfsNodeOps`].
+pub struct GenDirNode {
+    list: Box<ListFn>,
+    resolve: Box<ResolveFn>,
+    parent: RwLock<Weak<DirNode>>,
+}
+
+impl GenDirNode {
+    /// Creates a new generated directory.
+    ///
+    /// `list` returns the names and types of the current entries, and
+    /// `resolve` returns the node for a name, or `None` if there is none.
+    pub fn new(
+        list: impl Fn() -> Vec<(String, VfsNodeType)> + Send + Sync + 'static,
+        resolve: impl Fn(&str) -> Option<VfsNodeRef> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            list: Box::new(list),
+            resolve: Box::new(resolve),
+            parent: RwLock::new(Weak::new()),
+        }
+    }
+
+    pub(crate) fn set_parent(&self, parent: Weak<DirNode>) {
+        *self.parent.write() = parent;
+    }
+}
+
+impl VfsNodeOps for GenDirNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new_dir(4096, 0))
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.parent
+            .read()
+            .upgrade()
+            .map(|parent| parent as VfsNodeRef)
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        let node = match name {
+            "" | "." => Ok(self.clone() as VfsNodeRef),
+            ".." => self.parent().ok_or(VfsError::NotFound),
+            _ => (self.resolve)(name).ok_or(VfsError::NotFound),
+        }?;
+
+        if let Some(rest) = rest {
+            node.lookup(rest)
+        } else {
+            Ok(node)
+        }
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let entries = (self.list)();
+        let mut entries = entries.iter().skip(start_idx.max(2) - 2);
+        for (i, ent) in dirents.iter_mut().enumerate() {
+            match i + start_idx {
+                0 => *ent = VfsDirEntry::new(".", VfsNodeType::Dir),
+                1 => *ent = VfsDirEntry::new("..", VfsNodeType::Dir),
+                _ => {
+                    if let Some((name, ty)) = entries.next() {
+                        *ent = VfsDirEntry::new(name, *ty);
+                    } else {
+                        return Ok(i);
+                    }
+                }
+            }
+        }
+        Ok(dirents.len())
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        let (name, rest) = split_path(path);
+        if let Some(rest) = rest {
+            match name {
+                "" | "." => self.create(rest, ty),
+                _ => (self.resolve)(name)
+                    .ok_or(VfsError::NotFound)?
+                    .create(rest, ty),
+            }
+        } else if name.is_empty() || name == "." || name == ".." {
+            Ok(()) // already exists
+        } else {
+            Err(VfsError::PermissionDenied) // entries are generated, not created
+        }
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        let (name, rest) = split_path(path);
+        if let Some(rest) = rest {
+            match name {
+                "" | "." => self.remove(rest),
+                _ => (self.resolve)(name).ok_or(VfsError::NotFound)?.remove(rest),
+            }
+        } else {
+            Err(VfsError::PermissionDenied) // entries are generated, not removed
+        }
+    }
+
+    axfs_vfs::impl_vfs_dir_default! {}
+}
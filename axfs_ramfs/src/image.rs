@@ -0,0 +1,182 @@
+//! A binary image of a whole RAM filesystem.
+//!
+//! The image starts with the 4-byte magic `RFSI` and a version byte
+//! (currently `1`), followed by the root directory encoded as a node. All
+//! integers are little-endian. A node is encoded as:
+//!
+//! | Field | Size | Description |
+//! | --- | --- | --- |
+//! | type | 1 | [`VfsNodeType`] as its `repr(u8)` value |
+//! | mode | 2 | Permission bits |
+//! | body | variable | Depends on the type, see below |
+//!
+//! The body of a file is its size as a `u64` followed by the content, and
+//! the body of a symbolic link is the length of its target as a `u16`
+//! followed by the UTF-8 encoded target. The body of a directory is the
+//! number of entries as a `u32`, followed for each entry by the length of
+//! its name as a `u16`, the UTF-8 encoded name and the node.
+
+use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use axfs_vfs::{VfsError, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsResult};
+
+use crate::dir::DirNode;
+use crate::downcast_node;
+use crate::file::FileNode;
+use crate::listing::{node_type_from_u8, Reader};
+use crate::symlink::SymlinkNode;
+
+const MAGIC: &[u8; 4] = b"RFSI";
+const VERSION: u8 = 1;
+
+/// Encodes the tree under `root`.
+///
+/// Nodes that are not files, directories or static symbolic links of this
+/// crate are skipped, as are directories already encoded, which only occur in a
+/// cycle. Hard links are encoded as separate files.
+///
+/// Returns [`VfsError::NameTooLong`] if a name or symbolic link target does
+/// not fit in its `u16` length, and [`VfsError::StorageFull`] if a directory
+/// has more entries than fit in a `u32`.
+pub(crate) fn encode(root: &DirNode) -> VfsResult<Vec<u8>> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(MAGIC);
+    buf.push(VERSION);
+    let mut visited = BTreeSet::new();
+    visited.insert(root as *const DirNode as usize);
+    encode_dir(&mut buf, root, &mut visited)?;
+    Ok(buf)
+}
+
+fn encode_dir(buf: &mut Vec<u8>, dir: &DirNode, visited: &mut BTreeSet<usize>) -> VfsResult {
+    push_header(
+        buf,
+        VfsNodeType::Dir,
+        dir.get_attr().map(|attr| attr.perm()),
+    );
+    let count_pos = buf.len();
+    buf.extend_from_slice(&0u32.to_le_bytes());
+    let mut count = 0u32;
+    for name in dir.get_entries() {
+        let Ok(node) = dir.traverse_path(&name) else {
+            continue; // removed concurrently
+        };
+        let any = node.as_any();
+        let encodable = match any.downcast_ref::<DirNode>() {
+            Some(dir) => visited.insert(dir as *const DirNode as usize),
+            None => match any.downcast_ref::<SymlinkNode>() {
+                Some(link) => !link.is_dynamic(),
+                None => any.is::<FileNode>(),
+            },
+        };
+        if !encodable {
+            log::warn!("image: skipping {name}");
+            continue;
+        }
+        let name_len = u16::try_from(name.len()).map_err(|_| VfsError::NameTooLong)?;
+        buf.extend_from_slice(&name_len.to_le_bytes());
+        buf.extend_from_slice(name.as_bytes());
+        encode_node(buf, &node, visited)?;
+        count = count.checked_add(1).ok_or(VfsError::StorageFull)?;
+    }
+    buf[count_pos..count_pos + 4].copy_from_slice(&count.to_le_bytes());
+    Ok(())
+}
+
+fn encode_node(buf: &mut Vec<u8>, node: &VfsNodeRef, visited: &mut BTreeSet<usize>) -> VfsResult {
+    let any = node.as_any();
+    let perm = node.get_attr().map(|attr| attr.perm());
+    if let Some(dir) = any.downcast_ref::<DirNode>() {
+        encode_dir(buf, dir, visited)?;
+    } else if let Some(file) = any.downcast_ref::<FileNode>() {
+        push_header(buf, VfsNodeType::File, perm);
+        let data = file.content();
+        buf.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        buf.extend_from_slice(&data);
+    } else if let Some(Ok(target)) = any.downcast_ref::<SymlinkNode>().map(SymlinkNode::target) {
+        let target_len = u16::try_from(target.len()).map_err(|_| VfsError::NameTooLong)?;
+        push_header(buf, VfsNodeType::SymLink, perm);
+        buf.extend_from_slice(&target_len.to_le_bytes());
+        buf.extend_from_slice(target.as_bytes());
+    }
+    Ok(())
+}
+
+fn push_header(buf: &mut Vec<u8>, ty: VfsNodeType, perm: VfsResult<VfsNodePerm>) {
+    buf.push(ty as u8);
+    let perm = perm.unwrap_or_else(|_| VfsNodePerm::default_file());
+    buf.extend_from_slice(&perm.bits().to_le_bytes());
+}
+
+/// Decodes an image produced by [`encode`] into the empty directory `root`.
+///
+/// Returns [`VfsError::InvalidData`] if the data is malformed or has an
+/// unsupported version.
+pub(crate) fn decode(root: &Arc<DirNode>, data: &[u8]) -> VfsResult {
+    let mut reader = Reader(data);
+    if reader.take(MAGIC.len())? != MAGIC || reader.take(1)?[0] != VERSION {
+        return Err(VfsError::InvalidData);
+    }
+    let (ty, perm) = read_header(&mut reader)?;
+    if ty != VfsNodeType::Dir {
+        return Err(VfsError::InvalidData);
+    }
+    decode_dir(&mut reader, root)?;
+    set_perm(&**root, perm)?;
+    if !reader.0.is_empty() {
+        return Err(VfsError::InvalidData);
+    }
+    Ok(())
+}
+
+fn decode_dir(reader: &mut Reader, dir: &Arc<DirNode>) -> VfsResult {
+    let count = u32::from_le_bytes(reader.array()?);
+    for _ in 0..count {
+        let name_len = u16::from_le_bytes(reader.array()?);
+        let name = read_str(reader, name_len as usize)?;
+        if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+            return Err(VfsError::InvalidData);
+        }
+        let (ty, perm) = read_header(reader)?;
+        let node: VfsNodeRef = match ty {
+            VfsNodeType::Dir => {
+                dir.create_node(name, VfsNodeType::Dir)?;
+                let subdir = downcast_node(dir.traverse_path(name)?).ok_or(VfsError::NotFound)?;
+                decode_dir(reader, &subdir)?;
+                subdir
+            }
+            VfsNodeType::File => {
+                let size = u64::from_le_bytes(reader.array()?);
+                let size = usize::try_from(size).map_err(|_| VfsError::InvalidData)?;
+                dir.create_file(name, reader.take(size)?.to_vec())?
+            }
+            VfsNodeType::SymLink => {
+                let len = u16::from_le_bytes(reader.array()?);
+                dir.create_symlink(name, read_str(reader, len as usize)?)?;
+                dir.traverse_path(name)?
+            }
+            _ => return Err(VfsError::InvalidData),
+        };
+        set_perm(&*node, perm)?;
+    }
+    Ok(())
+}
+
+fn read_header(reader: &mut Reader) -> VfsResult<(VfsNodeType, VfsNodePerm)> {
+    let ty = node_type_from_u8(reader.take(1)?[0])?;
+    let perm =
+        VfsNodePerm::from_bits(u16::from_le_bytes(reader.array()?)).ok_or(VfsError::InvalidData)?;
+    Ok((ty, perm))
+}
+
+fn read_str<'a>(reader: &mut Reader<'a>, len: usize) -> VfsResult<&'a str> {
+    core::str::from_utf8(reader.take(len)?).map_err(|_| VfsError::InvalidData)
+}
+
+fn set_perm(node: &dyn VfsNodeOps, perm: VfsNodePerm) -> VfsResult {
+    let mut attr = node.get_attr()?;
+    attr.set_perm(perm);
+    node.set_attr(&attr)
+}
@@ -6,32 +6,296 @@
 
 extern crate alloc;
 
+mod context;
 mod dir;
+mod entries;
+mod fifo;
 mod file;
+mod gen_dir;
+mod image;
+mod listing;
+mod meta;
+mod sealed;
+mod symlink;
+mod tar;
 
 #[cfg(test)]
 mod tests;
 
-pub use self::dir::DirNode;
+pub use self::context::{AccessMode, RamFsStats};
+pub use self::dir::{
+    ConflictPolicy, DirNode, FsEvent, LookupChain, LookupError, LookupFailure, RemovalReport,
+};
+pub use self::fifo::FifoNode;
 pub use self::file::FileNode;
+pub use self::gen_dir::GenDirNode;
+pub use self::listing::{decode_listing, ListingEntry};
+pub use self::sealed::SealedRamFs;
+pub use self::symlink::SymlinkNode;
+pub use self::tar::TarLimits;
 
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::String;
 use alloc::sync::Arc;
-use axfs_vfs::{VfsNodeOps, VfsNodeRef, VfsOps, VfsResult};
+use alloc::{vec, vec::Vec};
+use axfs_vfs::path::canonicalize;
+use axfs_vfs::{VfsError, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsOps, VfsResult};
 use spin::once::Once;
 
+use self::context::FsContext;
+
+/// The maximum number of symbolic links followed by
+/// [`RamFileSystem::resolve_symlink`], as in Linux.
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+/// A node to create with [`RamFileSystem::from_manifest`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsEntry<'a> {
+    /// An empty directory.
+    Dir,
+    /// A regular file with the given content.
+    File(&'a [u8]),
+    /// A symbolic link with the given target.
+    Symlink(&'a str),
+}
+
 /// A RAM filesystem that implements [`axfs_vfs::VfsOps`].
 pub struct RamFileSystem {
     parent: Once<VfsNodeRef>,
+    ctx: Arc<FsContext>,
     root: Arc<DirNode>,
 }
 
 impl RamFileSystem {
     /// Create a new instance.
     pub fn new() -> Self {
+        Self::with_context(Arc::new(FsContext::new()))
+    }
+
+    /// Creates a new instance whose lookups ignore case, like a FAT
+    /// filesystem.
+    ///
+    /// Entries keep the casing they were created with, which is what
+    /// directory listings show, but can be looked up, replaced and removed
+    /// by any casing. Creating `Foo` where `foo` exists fails with
+    /// [`VfsError::AlreadyExists`].
+    pub fn new_case_insensitive() -> Self {
+        let ctx = FsContext::new();
+        ctx.set_case_insensitive(true);
+        Self::with_context(Arc::new(ctx))
+    }
+
+    fn with_context(ctx: Arc<FsContext>) -> Self {
         Self {
             parent: Once::new(),
-            root: DirNode::new(None),
+            root: DirNode::new(None, ctx.clone()),
+            ctx,
+        }
+    }
+
+    /// Creates a new instance holding at most `capacity` bytes of file
+    /// content.
+    ///
+    /// Writes that would exceed the capacity fail with
+    /// [`VfsError::StorageFull`] and leave the file unchanged. Directories do
+    /// not count against the capacity.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let fs = Self::new();
+        fs.ctx.set_capacity(capacity);
+        fs
+    }
+
+    /// Creates a new instance populated from a map of absolute file paths to
+    /// file contents.
+    ///
+    /// Intermediate directories are created as needed, and keys ending with
+    /// `/` create (empty) directories; their values are ignored. Keys ending
+    /// with `//` create symbolic links to the targets in their values, as
+    /// returned by [`to_map`](Self::to_map).
+    pub fn from_map(files: BTreeMap<String, Vec<u8>>) -> VfsResult<Self> {
+        let fs = Self::new();
+        for (path, content) in files {
+            let link = path.strip_suffix("//");
+            if link.is_none() && path.ends_with('/') {
+                fs.root.create_dir_all(&path)?;
+                continue;
+            }
+            let path = canonicalize(link.unwrap_or(&path));
+            let (dir, name) = path.rsplit_once('/').unwrap_or(("", &path));
+            if name.is_empty() {
+                return Err(VfsError::InvalidInput);
+            }
+            let dir = fs.root.create_dir_all(dir)?;
+            match link {
+                Some(_) => {
+                    let target = String::from_utf8(content).map_err(|_| VfsError::InvalidData)?;
+                    dir.create_symlink(name, &target)?;
+                }
+                None => drop(dir.create_file(name, content)?),
+            }
+        }
+        Ok(fs)
+    }
+
+    /// Creates a new instance populated from a list of paths and the nodes to
+    /// create at them, e.g. to declare a test fixture.
+    ///
+    /// Intermediate directories are created as needed, so the entries may be
+    /// given in any order. Returns [`VfsError::AlreadyExists`] if a path is
+    /// given twice.
+    pub fn from_manifest(entries: &[(&str, FsEntry)]) -> VfsResult<Self> {
+        let mut paths = BTreeSet::new();
+        for (path, _) in entries {
+            if !paths.insert(canonicalize(path)) {
+                return Err(VfsError::AlreadyExists);
+            }
+        }
+        let fs = Self::new();
+        for (path, entry) in entries {
+            let path = canonicalize(path);
+            if let FsEntry::Dir = entry {
+                fs.root.create_dir_all(&path)?;
+                continue;
+            }
+            let (dir, name) = path.rsplit_once('/').unwrap_or(("", &path));
+            if name.is_empty() {
+                return Err(VfsError::InvalidInput);
+            }
+            let dir = fs.root.create_dir_all(dir)?;
+            match entry {
+                FsEntry::File(content) => {
+                    dir.create_file(name, content.to_vec())?;
+                }
+                FsEntry::Symlink(target) => dir.create_symlink(name, target)?,
+                FsEntry::Dir => unreachable!(),
+            }
         }
+        Ok(fs)
+    }
+
+    /// Creates a new instance populated from a ustar archive.
+    ///
+    /// This is [`from_tar_limited`](Self::from_tar_limited) with no limits.
+    pub fn from_tar(data: &[u8]) -> VfsResult<Self> {
+        Self::from_tar_limited(data, TarLimits::default())
+    }
+
+    /// Creates a new instance populated from a ustar archive, failing
+    /// partway if the archive exceeds `limits`.
+    ///
+    /// Directories, regular files, symlinks and their intermediate
+    /// directories are created; other entry types are skipped with a
+    /// warning. Exceeding the size or file count limit fails with
+    /// [`VfsError::StorageFull`], exceeding the depth limit with
+    /// [`VfsError::NameTooLong`], and a malformed archive with
+    /// [`VfsError::InvalidData`]. On failure, everything extracted so far is
+    /// dropped.
+    pub fn from_tar_limited(data: &[u8], limits: TarLimits) -> VfsResult<Self> {
+        let fs = Self::new();
+        tar::extract(&fs.root, data, &limits)?;
+        Ok(fs)
+    }
+
+    /// Encodes the filesystem into a self-contained binary image, which can
+    /// be loaded with [`import`](Self::import), e.g. on the next boot.
+    ///
+    /// Directories, files and static symbolic links are encoded along with
+    /// their permissions. Other nodes, such as dynamic symbolic links and
+    /// devices added with [`add_node`](VfsNodeOps::add_node), are skipped
+    /// with a warning, and
+    /// hard links are imported as separate files.
+    ///
+    /// Returns [`VfsError::NameTooLong`] if a name or symbolic link target is
+    /// longer than `u16::MAX` bytes, which the image cannot hold.
+    pub fn export(&self) -> VfsResult<Vec<u8>> {
+        image::encode(&self.root)
+    }
+
+    /// Creates a new instance from an image produced by
+    /// [`export`](Self::export).
+    ///
+    /// Returns [`VfsError::InvalidData`] if the image is malformed or was
+    /// produced by an incompatible version.
+    pub fn import(data: &[u8]) -> VfsResult<Self> {
+        let fs = Self::new();
+        image::decode(&fs.root, data)?;
+        Ok(fs)
+    }
+
+    /// Extracts a ustar archive into the filesystem, like an initramfs.
+    ///
+    /// Directories, regular files and symlinks are created along with their
+    /// missing ancestors, in any order, and an entry replaces an existing
+    /// non-directory node of the same path. Other entry types, such as
+    /// devices and fifos, are skipped with a warning. A malformed archive,
+    /// or a file or symlink entry at the path of a directory, fails with
+    /// [`VfsError::InvalidData`], leaving the entries before the error in
+    /// place.
+    pub fn load_tar(&self, data: &[u8]) -> VfsResult {
+        tar::extract(&self.root, data, &TarLimits::default())
+    }
+
+    /// Removes everything from the filesystem, leaving an empty root
+    /// directory, and resets the peak memory usage.
+    ///
+    /// The root node and its mount point stay the same, so a mounted
+    /// filesystem remains usable. Nodes still referenced elsewhere, e.g. by
+    /// open files, stay valid but are no longer reachable from the root.
+    /// Fails with [`VfsError::ReadOnlyFilesystem`] if the root is read-only.
+    pub fn clear(&self) -> VfsResult {
+        self.root.detach_all()?;
+        self.ctx.reset_peak();
+        Ok(())
+    }
+
+    /// Returns a flat view of the filesystem, mapping the absolute path of
+    /// every file to a copy of its content.
+    ///
+    /// Directories are included as keys ending with `/` and empty values,
+    /// and symbolic links as keys ending with `//` mapped to their targets.
+    /// Nodes of other types are skipped.
+    pub fn to_map(&self) -> BTreeMap<String, Vec<u8>> {
+        let mut map = BTreeMap::new();
+        self.root.walk(&mut |path, node| {
+            if let Some(file) = node.as_any().downcast_ref::<FileNode>() {
+                map.insert(format!("/{path}"), file.content().to_vec());
+            } else if node.as_any().is::<DirNode>() {
+                map.insert(format!("/{path}/"), Vec::new());
+            } else if let Some(link) = node.as_any().downcast_ref::<SymlinkNode>() {
+                let target = link.target().unwrap_or_default();
+                map.insert(format!("/{path}//"), target.into_bytes());
+            }
+        });
+        map
+    }
+
+    /// Returns a deep copy of the filesystem, e.g. to restore it later.
+    ///
+    /// The copy has the same settings and tree, and the contents of all
+    /// files are copied on write, so writes to either filesystem are not
+    /// visible in the other. Nodes that are not files, directories or symbolic links of
+    /// this crate, such as devices added with
+    /// [`add_node`](VfsNodeOps::add_node), cannot be copied and are shared.
+    /// Dynamic symbolic links share their callback with the original.
+    /// Timestamps and inode numbers are not preserved.
+    pub fn snapshot(&self) -> VfsResult<Self> {
+        let ctx = self.ctx.clone_settings();
+        ctx.set_readonly(false); // restored once the tree is copied
+        let fs = Self::with_context(Arc::new(ctx));
+        fs.root.copy_attr(&self.root.get_attr()?);
+        self.root.copy_into(&fs.root)?;
+        fs.set_readonly(self.is_readonly());
+        Ok(fs)
+    }
+
+    /// Checks whether two filesystems have the same tree structure, file
+    /// contents and symbolic link targets.
+    ///
+    /// Metadata such as timestamps, permissions and inode numbers is
+    /// ignored, as is the order in which the nodes were created.
+    pub fn structural_eq(&self, other: &Self) -> bool {
+        self.to_map() == other.to_map()
     }
 
     /// Returns the root directory node in [`Arc<DirNode>`](DirNode).
@@ -39,12 +303,365 @@ impl RamFileSystem {
         self.root.clone()
     }
 
+    /// Consumes the filesystem and returns an immutable handle to it that
+    /// can be shared between many readers.
+    ///
+    /// The filesystem is made read-only, so nodes kept from before sealing
+    /// cannot be mutated either.
+    pub fn seal_all(self) -> Arc<SealedRamFs> {
+        Arc::new(SealedRamFs::new(self))
+    }
+
+    /// Sets whether creating a node through [`VfsNodeOps::create`] or
+    /// [`VfsNodeOps::symlink`] also creates the missing intermediate
+    /// directories of its path.
+    ///
+    /// An intermediate component that exists but is not a directory still
+    /// fails with [`VfsError::NotADirectory`]. Disabled by default.
+    pub fn set_auto_mkdir(&self, enabled: bool) {
+        self.ctx.set_auto_mkdir(enabled)
+    }
+
+    /// Sets the clock used to timestamp nodes, returning the current time in
+    /// nanoseconds.
+    ///
+    /// New nodes get all their timestamps set to the current time. Reading a
+    /// file or listing a directory updates its access time, writing a file or
+    /// changing the entries of a directory updates its modification and
+    /// change times, and changing metadata updates the change time only.
+    /// Without a time source, all timestamps are `0`.
+    pub fn set_time_source(&self, source: impl Fn() -> u64 + Send + Sync + 'static) {
+        self.ctx.set_time_source(Arc::new(source))
+    }
+
+    /// Sets whether character and block device nodes are forbidden, like the
+    /// `nodev` mount option.
+    ///
+    /// While set, adding such a node fails with
+    /// [`VfsError::PermissionDenied`]. Existing nodes are not affected.
+    pub fn set_nodev(&self, enabled: bool) {
+        self.ctx.set_nodev(enabled)
+    }
+
+    /// Sets whether files may not be executable, like the `noexec` mount
+    /// option.
+    ///
+    /// While set, the execute permission bits are cleared from the
+    /// permission of new files and from permission changes of files.
+    pub fn set_noexec(&self, enabled: bool) {
+        self.ctx.set_noexec(enabled)
+    }
+
+    /// Installs a callback consulted before every file read or write and
+    /// every directory listing or lookup in this filesystem.
+    ///
+    /// The access fails with [`VfsError::PermissionDenied`] if `checker`
+    /// returns `false`. No checker is installed by default.
+    pub fn set_access_checker(
+        &self,
+        checker: impl Fn(&VfsNodeRef, AccessMode) -> bool + Send + Sync + 'static,
+    ) {
+        self.ctx.set_access_checker(Some(Arc::new(checker)))
+    }
+
+    /// Removes the callback installed by
+    /// [`set_access_checker`](Self::set_access_checker).
+    pub fn clear_access_checker(&self) {
+        self.ctx.set_access_checker(None)
+    }
+
+    /// Returns the absolute paths of the directory entries that refer back
+    /// to one of their own ancestors, making the tree cyclic.
+    ///
+    /// Such cycles cannot be created by renaming, and
+    /// [`VfsNodeOps::add_node`] refuses to add a directory under itself, but
+    /// a directory added in several places can still end up inside itself.
+    pub fn detect_cycles(&self) -> Vec<String> {
+        self.root.find_cycles()
+    }
+
+    /// Detaches the entry at `path` from its parent directory, without
+    /// requiring it to be empty.
+    ///
+    /// This is meant to break a cycle reported by
+    /// [`detect_cycles`](Self::detect_cycles).
+    pub fn break_cycle(&self, path: &str) -> VfsResult {
+        let (dir, name) = self.root.resolve_parent(path)?;
+        dir.detach(name).map(drop)
+    }
+
+    /// Returns the node with the given inode number, if any.
+    ///
+    /// This walks the whole tree. A file with several hard links is
+    /// returned once, through whichever link is found first.
+    pub fn find_by_ino(&self, ino: u64) -> Option<VfsNodeRef> {
+        if self.root.get_attr().ok()?.ino() == ino {
+            return Some(self.root.clone());
+        }
+        let mut found = None;
+        self.root.walk(&mut |_, node| {
+            if found.is_none() && node.get_attr().is_ok_and(|attr| attr.ino() == ino) {
+                found = Some(node.clone());
+            }
+        });
+        found
+    }
+
+    /// Returns the absolute path of `node` in this filesystem, if it is
+    /// reachable from the root.
+    ///
+    /// The path is built by walking up the parent directories, so it is only
+    /// found for directories and files, not for other nodes. A file is found
+    /// through the directory it was created in or last moved to, and a hard
+    /// link elsewhere is not considered.
+    pub fn path_of(&self, node: &VfsNodeRef) -> Option<String> {
+        let root = self.root.clone() as VfsNodeRef;
+        let mut names = Vec::new();
+        let mut cur = node.clone();
+        while !Arc::ptr_eq(&cur, &root) {
+            let any = cur.as_any();
+            let parent = match any.downcast_ref::<FileNode>() {
+                Some(file) => file.dir()?,
+                None => any
+                    .downcast_ref::<DirNode>()?
+                    .parent()
+                    .and_then(downcast_node::<DirNode>)?,
+            };
+            names.push(parent.name_of(&cur)?);
+            cur = parent;
+        }
+        if names.is_empty() {
+            return Some("/".into());
+        }
+        Some(names.iter().rev().map(|name| format!("/{name}")).collect())
+    }
+
+    /// Returns the total bytes of file content currently held.
+    pub fn memory_usage(&self) -> usize {
+        self.ctx.memory_usage()
+    }
+
+    /// Sets the maximum length in bytes of a symbolic link target, 4096 by
+    /// default.
+    ///
+    /// Creating a link with a longer target fails with
+    /// [`VfsError::NameTooLong`], as does reading a dynamic link whose
+    /// callback generates one. Existing links are not affected.
+    pub fn set_max_symlink_len(&self, len: usize) {
+        self.ctx.set_max_symlink_len(len)
+    }
+
+    /// Limits the number of entries of every directory to `max`, unlimited by
+    /// default.
+    ///
+    /// Adding an entry to a full directory fails with
+    /// [`VfsError::StorageFull`]. A directory can override the limit with
+    /// [`DirNode::set_max_entries`].
+    pub fn set_max_dir_entries(&self, max: usize) {
+        self.ctx.set_max_dir_entries(max)
+    }
+
+    /// Makes the whole filesystem read-only, e.g. to protect a baked-in
+    /// image, or writable again.
+    ///
+    /// While read-only, creating, removing, renaming and linking entries,
+    /// writing or truncating files, and changing attributes fail with
+    /// [`VfsError::ReadOnlyFilesystem`], even for files that are no longer
+    /// in the tree. Lookups, reads and listings work as usual.
+    pub fn set_readonly(&self, readonly: bool) {
+        self.ctx.set_readonly(readonly);
+    }
+
+    /// Checks whether the filesystem has been made read-only with
+    /// [`set_readonly`](Self::set_readonly).
+    pub fn is_readonly(&self) -> bool {
+        self.ctx.readonly()
+    }
+
+    /// Returns usage statistics of the filesystem.
+    ///
+    /// The numbers are maintained as nodes are created and dropped, so this
+    /// is O(1). Nodes that were removed from the tree but are still
+    /// referenced, e.g. by an open file, are counted until they are dropped,
+    /// and hard links to the same file are counted once.
+    pub fn stats(&self) -> RamFsStats {
+        self.ctx.stats()
+    }
+
+    /// Returns the maximum bytes of file content the filesystem can hold, as
+    /// set by [`with_capacity`](Self::with_capacity).
+    pub fn capacity(&self) -> usize {
+        self.ctx.capacity()
+    }
+
+    /// Returns the maximum of [`memory_usage`](Self::memory_usage) since the
+    /// filesystem was created or [`reset_peak`](Self::reset_peak) was
+    /// last called.
+    pub fn peak_memory_usage(&self) -> usize {
+        self.ctx.peak_memory_usage()
+    }
+
+    /// Resets the peak memory usage to the current usage.
+    pub fn reset_peak(&self) {
+        self.ctx.reset_peak()
+    }
+
     /// Add a node to the root directory.
     ///
     /// The node must implement [`axfs_vfs::VfsNodeOps`], and be wrapped in [`Arc`].
     pub fn add(&self, name: &'static str, node: VfsNodeRef) {
         let _ = self.root.add_node(name, node);
     }
+
+    /// Truncates every regular file under the directory at `path` to `size`,
+    /// and returns the number of files truncated.
+    ///
+    /// The directory structure is left intact.
+    pub fn truncate_subtree(&self, path: &str, size: u64) -> VfsResult<usize> {
+        let dir = self.root.clone().lookup(path)?;
+        let dir = dir
+            .as_any()
+            .downcast_ref::<DirNode>()
+            .ok_or(VfsError::NotADirectory)?;
+        let mut count = 0;
+        let mut res = Ok(());
+        dir.walk(&mut |_, node| {
+            if res.is_ok() && node.get_attr().is_ok_and(|attr| attr.is_file()) {
+                res = node.truncate(size);
+                count += 1;
+            }
+        });
+        res.map(|_| count)
+    }
+
+    /// Returns the node the symbolic link at `link_path` points to.
+    ///
+    /// A relative target is resolved against the directory containing the
+    /// link, including its `.` and `..` components, and an absolute one
+    /// against the root. Links in the target are followed as well, up to 40
+    /// in total, after which [`VfsError::FilesystemLoop`] is returned.
+    /// Returns [`VfsError::InvalidInput`] if `link_path` is not a symbolic
+    /// link.
+    pub fn resolve_symlink(&self, link_path: &str) -> VfsResult<VfsNodeRef> {
+        if !self.root.clone().lookup(link_path)?.is_symlink() {
+            return Err(VfsError::InvalidInput);
+        }
+        self.root
+            .clone()
+            .lookup_follow(link_path, MAX_SYMLINK_DEPTH)
+    }
+
+    /// Returns the content of the file at `path` as a string.
+    ///
+    /// Returns [`VfsError::InvalidData`] if the content is not valid UTF-8,
+    /// and [`VfsError::IsADirectory`] if `path` is a directory.
+    pub fn read_to_string(&self, path: &str) -> VfsResult<String> {
+        let node = self.root.clone().lookup(path)?;
+        if node.get_attr()?.is_dir() {
+            return Err(VfsError::IsADirectory);
+        }
+        downcast_node::<FileNode>(node)
+            .ok_or(VfsError::Unsupported)?
+            .read_to_string()
+    }
+
+    /// Removes all empty directories under the directory at `path`, and
+    /// returns the number removed.
+    ///
+    /// Directories are visited bottom-up, so one that only contains empty
+    /// directories is removed as well. Other nodes and the directory at
+    /// `path` itself are never removed.
+    pub fn prune_empty_dirs(&self, path: &str) -> VfsResult<usize> {
+        let dir = self.root.clone().lookup(path)?;
+        dir.as_any()
+            .downcast_ref::<DirNode>()
+            .ok_or(VfsError::NotADirectory)?
+            .prune_empty_dirs()
+    }
+
+    /// Looks up each of `paths` from the root directory and returns its
+    /// attributes, or the error that occurred, in the same order.
+    pub fn stat_many(&self, paths: &[&str]) -> Vec<VfsResult<VfsNodeAttr>> {
+        let root = self.root_dir();
+        paths
+            .iter()
+            .map(|path| root.clone().lookup(path)?.get_attr())
+            .collect()
+    }
+
+    /// Resolves `path` from the root directory, and returns the node together
+    /// with the directory containing it (`None` for the root itself).
+    pub fn resolve_with_parent(&self, path: &str) -> VfsResult<(Option<Arc<DirNode>>, VfsNodeRef)> {
+        let path = canonicalize(path);
+        let path = path.trim_start_matches('/');
+        if path.is_empty() {
+            return Ok((None, self.root_dir()));
+        }
+        let (parent, name) = self.root.resolve_parent(path)?;
+        let node = parent.traverse_path(name)?;
+        Ok((Some(parent), node))
+    }
+
+    /// Computes the relative path from the directory `from` to `to`, e.g.
+    /// `../c/d` from `/a/b` to `/a/c/d`.
+    ///
+    /// The computation is purely lexical: both paths are normalized first, but
+    /// they do not need to exist. Returns `None` if either path is not
+    /// absolute.
+    pub fn relative_path(&self, from: &str, to: &str) -> Option<String> {
+        if !from.starts_with('/') || !to.starts_with('/') {
+            return None;
+        }
+        let (from, to) = (canonicalize(from), canonicalize(to));
+        let from: Vec<_> = from.split('/').filter(|s| !s.is_empty()).collect();
+        let to: Vec<_> = to.split('/').filter(|s| !s.is_empty()).collect();
+        let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+        let mut parts = vec![".."; from.len() - common];
+        parts.extend_from_slice(&to[common..]);
+        if parts.is_empty() {
+            Some(".".into())
+        } else {
+            Some(parts.join("/"))
+        }
+    }
+
+    /// Renames or moves the node at `src_path` to `dst_path`, both relative
+    /// to the root directory.
+    ///
+    /// Like `rename(2)`, an existing node at `dst_path` is replaced if the
+    /// types are compatible (a directory may only replace an empty directory).
+    /// Renaming a node onto itself is a no-op, and moving a directory into
+    /// its own subtree fails with [`VfsError::InvalidInput`].
+    ///
+    /// [`VfsError::InvalidInput`]: axfs_vfs::VfsError::InvalidInput
+    pub fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        VfsNodeOps::rename(&*self.root, src_path, dst_path)
+    }
+
+    /// Creates a hard link at `dst_path` to the file at `src_path`, both
+    /// relative to the root directory, like `link(2)`.
+    ///
+    /// Both paths then refer to the same node, whose link count reported by
+    /// [`get_attr`](VfsNodeOps::get_attr) is incremented. Fails with
+    /// [`VfsError::AlreadyExists`] if `dst_path` exists, and with
+    /// [`VfsError::PermissionDenied`] if `src_path` is not a regular file.
+    pub fn link(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        VfsNodeOps::link(&*self.root, src_path, dst_path)
+    }
+
+    /// Renames the file at `path` to `to` and creates a new empty file at
+    /// `path`, as a single atomic step.
+    ///
+    /// There is no moment where `path` does not exist, so writers that open
+    /// it by name never see [`VfsError::NotFound`]. An existing file at `to`
+    /// is replaced. Fails with [`VfsError::IsADirectory`] if `path` is a
+    /// directory.
+    pub fn rotate(&self, path: &str, to: &str) -> VfsResult {
+        let (src_dir, src_name) = self.root.resolve_parent(path)?;
+        let (dst_dir, dst_name) = self.root.resolve_parent(to)?;
+        DirNode::rotate_file(&src_dir, src_name, &dst_dir, dst_name)
+    }
 }
 
 impl VfsOps for RamFileSystem {
@@ -67,3 +684,47 @@ impl Default for RamFileSystem {
         Self::new()
     }
 }
+
+/// Converts a [`VfsNodeRef`] to the concrete node type `T`, if it is one.
+pub(crate) fn downcast_node<T: VfsNodeOps + 'static>(node: VfsNodeRef) -> Option<Arc<T>> {
+    if node.as_any().is::<T>() {
+        // SAFETY: the pointee has just been checked to be a `T`.
+        Some(unsafe { Arc::from_raw(Arc::into_raw(node) as *const T) })
+    } else {
+        None
+    }
+}
+
+/// Returns the deepest directory that is an ancestor of (or equal to) all of
+/// `paths`, after canonicalizing them.
+///
+/// This is a purely lexical computation. Absolute paths with nothing else in
+/// common share `/`, while relative ones share the empty path. An empty
+/// string is also returned for no paths, or a mix of absolute and relative
+/// paths.
+pub fn common_prefix(paths: &[&str]) -> String {
+    let Some(first) = paths.first() else {
+        return String::new();
+    };
+    let absolute = first.starts_with('/');
+    if paths.iter().any(|path| path.starts_with('/') != absolute) {
+        return String::new();
+    }
+    let paths: Vec<_> = paths.iter().map(|path| canonicalize(path)).collect();
+    let mut common: Vec<_> = paths[0].split('/').filter(|s| !s.is_empty()).collect();
+    for path in &paths[1..] {
+        let parts = path.split('/').filter(|s| !s.is_empty());
+        let len = common
+            .iter()
+            .zip(parts)
+            .take_while(|(a, b)| **a == *b)
+            .count();
+        common.truncate(len);
+    }
+    let common = common.join("/");
+    if absolute {
+        format!("/{common}")
+    } else {
+        common
+    }
+}
@@ -0,0 +1,114 @@
+//! A compact binary encoding of directory listings.
+//!
+//! The encoding starts with the 4-byte magic `RFSL` and a version byte
+//! (currently `1`), followed by the number of entries as a little-endian
+//! `u32`. Each entry is then encoded as:
+//!
+//! | Field | Size | Description |
+//! | --- | --- | --- |
+//! | type | 1 | [`VfsNodeType`] as its `repr(u8)` value |
+//! | size | 8 | Node size in bytes, little-endian |
+//! | ino | 8 | Inode number, little-endian |
+//! | name length | 2 | Length of the name in bytes, little-endian |
+//! | name | variable | UTF-8 encoded name |
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use axfs_vfs::{VfsError, VfsNodeType, VfsResult};
+
+const MAGIC: &[u8; 4] = b"RFSL";
+const VERSION: u8 = 1;
+
+/// An entry of a directory listing produced by
+/// [`DirNode::encode_listing`](crate::DirNode::encode_listing).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListingEntry {
+    /// Name of the entry.
+    pub name: String,
+    /// Type of the node.
+    pub ty: VfsNodeType,
+    /// Size of the node in bytes.
+    pub size: u64,
+    /// Inode number of the node.
+    pub ino: u64,
+}
+
+pub(crate) fn encode(entries: &[ListingEntry]) -> VfsResult<Vec<u8>> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(MAGIC);
+    buf.push(VERSION);
+    let count = u32::try_from(entries.len()).map_err(|_| VfsError::StorageFull)?;
+    buf.extend_from_slice(&count.to_le_bytes());
+    for ent in entries {
+        let name_len = u16::try_from(ent.name.len()).map_err(|_| VfsError::NameTooLong)?;
+        buf.push(ent.ty as u8);
+        buf.extend_from_slice(&ent.size.to_le_bytes());
+        buf.extend_from_slice(&ent.ino.to_le_bytes());
+        buf.extend_from_slice(&name_len.to_le_bytes());
+        buf.extend_from_slice(ent.name.as_bytes());
+    }
+    Ok(buf)
+}
+
+/// Decodes a directory listing produced by
+/// [`DirNode::encode_listing`](crate::DirNode::encode_listing).
+///
+/// Returns [`VfsError::InvalidData`] if the data is malformed or has an
+/// unsupported version.
+pub fn decode_listing(data: &[u8]) -> VfsResult<Vec<ListingEntry>> {
+    let mut reader = Reader(data);
+    if reader.take(MAGIC.len())? != MAGIC || reader.take(1)?[0] != VERSION {
+        return Err(VfsError::InvalidData);
+    }
+    let count = u32::from_le_bytes(reader.array()?);
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let ty = node_type_from_u8(reader.take(1)?[0])?;
+        let size = u64::from_le_bytes(reader.array()?);
+        let ino = u64::from_le_bytes(reader.array()?);
+        let name_len = u16::from_le_bytes(reader.array()?);
+        let name = core::str::from_utf8(reader.take(name_len as usize)?)
+            .map_err(|_| VfsError::InvalidData)?;
+        entries.push(ListingEntry {
+            name: name.into(),
+            ty,
+            size,
+            ino,
+        });
+    }
+    if !reader.0.is_empty() {
+        return Err(VfsError::InvalidData);
+    }
+    Ok(entries)
+}
+
+pub(crate) struct Reader<'a>(pub &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub fn take(&mut self, len: usize) -> VfsResult<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(VfsError::InvalidData);
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> VfsResult<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+}
+
+pub(crate) fn node_type_from_u8(ty: u8) -> VfsResult<VfsNodeType> {
+    Ok(match ty {
+        0o1 => VfsNodeType::Fifo,
+        0o2 => VfsNodeType::CharDevice,
+        0o4 => VfsNodeType::Dir,
+        0o6 => VfsNodeType::BlockDevice,
+        0o10 => VfsNodeType::File,
+        0o12 => VfsNodeType::SymLink,
+        0o14 => VfsNodeType::Socket,
+        _ => return Err(VfsError::InvalidData),
+    })
+}
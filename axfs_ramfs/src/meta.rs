@@ -0,0 +1,162 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use axfs_vfs::{VfsError, VfsNodeAttr, VfsNodePerm, VfsNodeType, VfsResult};
+use spin::RwLock;
+
+/// Metadata shared by all node types in the RAM filesystem.
+pub(crate) struct NodeMeta {
+    ino: u64,
+    btime: u64,
+    atime: AtomicU64,
+    mtime: AtomicU64,
+    ctime: AtomicU64,
+    perm: RwLock<VfsNodePerm>,
+    uid: AtomicU32,
+    gid: AtomicU32,
+    version: AtomicU64,
+    user_data: RwLock<Option<Arc<dyn Any + Send + Sync>>>,
+    xattrs: RwLock<BTreeMap<String, Vec<u8>>>,
+}
+
+impl NodeMeta {
+    pub const fn new(ino: u64, perm: VfsNodePerm, now: u64) -> Self {
+        Self {
+            ino,
+            btime: now,
+            atime: AtomicU64::new(now),
+            mtime: AtomicU64::new(now),
+            ctime: AtomicU64::new(now),
+            perm: RwLock::new(perm),
+            uid: AtomicU32::new(0),
+            gid: AtomicU32::new(0),
+            version: AtomicU64::new(0),
+            user_data: RwLock::new(None),
+            xattrs: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Returns the attributes of the node with the given type and size.
+    pub fn attr(&self, ty: VfsNodeType, size: u64) -> VfsNodeAttr {
+        let mut attr = VfsNodeAttr::new(self.perm(), ty, size, 0);
+        attr.set_ino(self.ino);
+        attr.set_uid(self.uid.load(Ordering::Relaxed));
+        attr.set_gid(self.gid.load(Ordering::Relaxed));
+        attr.set_btime(self.btime);
+        attr.set_atime(self.atime.load(Ordering::Relaxed));
+        attr.set_mtime(self.mtime.load(Ordering::Relaxed));
+        attr.set_ctime(self.ctime.load(Ordering::Relaxed));
+        attr
+    }
+
+    /// Records that the content of the node was read at `now`.
+    ///
+    /// The version is left unchanged, so reads do not invalidate it.
+    pub fn touch_accessed(&self, now: u64) {
+        self.atime.store(now, Ordering::Relaxed);
+    }
+
+    /// Records that the content of the node was modified at `now`.
+    pub fn touch_modified(&self, now: u64) {
+        self.mtime.store(now, Ordering::Relaxed);
+        self.ctime.store(now, Ordering::Relaxed);
+        self.version.fetch_add(1, Ordering::AcqRel);
+    }
+
+    pub fn perm(&self) -> VfsNodePerm {
+        *self.perm.read()
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
+
+    pub fn set_user_data(&self, data: Arc<dyn Any + Send + Sync>) {
+        *self.user_data.write() = Some(data);
+    }
+
+    pub fn user_data(&self) -> Option<Arc<dyn Any + Send + Sync>> {
+        self.user_data.read().clone()
+    }
+
+    pub fn get_xattr(&self, name: &str, buf: &mut [u8]) -> VfsResult<usize> {
+        let xattrs = self.xattrs.read();
+        let value = xattrs.get(name).ok_or(VfsError::NotFound)?;
+        if buf.is_empty() {
+            return Ok(value.len());
+        }
+        let dst = buf.get_mut(..value.len()).ok_or(VfsError::OutOfRange)?;
+        dst.copy_from_slice(value);
+        Ok(value.len())
+    }
+
+    pub fn set_xattr(&self, name: &str, value: &[u8], now: u64) -> VfsResult {
+        if name.is_empty() {
+            return Err(VfsError::InvalidInput);
+        }
+        self.xattrs.write().insert(name.into(), value.into());
+        self.ctime.store(now, Ordering::Relaxed);
+        self.version.fetch_add(1, Ordering::AcqRel);
+        Ok(())
+    }
+
+    pub fn list_xattr(&self) -> Vec<String> {
+        self.xattrs.read().keys().cloned().collect()
+    }
+
+    pub fn remove_xattr(&self, name: &str, now: u64) -> VfsResult {
+        self.xattrs.write().remove(name).ok_or(VfsError::NotFound)?;
+        self.ctime.store(now, Ordering::Relaxed);
+        self.version.fetch_add(1, Ordering::AcqRel);
+        Ok(())
+    }
+
+    /// Applies the metadata in `attr` unconditionally.
+    pub fn set_attr(&self, attr: &VfsNodeAttr, now: u64) {
+        let mut perm = self.perm.write();
+        *perm = attr.perm();
+        self.set_owner(attr);
+        self.ctime.store(now, Ordering::Relaxed);
+        self.version.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Changes the owner, leaving the user or group ID unchanged if `None`.
+    pub fn chown(&self, uid: Option<u32>, gid: Option<u32>, now: u64) {
+        let _perm = self.perm.write(); // serializes with the other changes
+        if let Some(uid) = uid {
+            self.uid.store(uid, Ordering::Relaxed);
+        }
+        if let Some(gid) = gid {
+            self.gid.store(gid, Ordering::Relaxed);
+        }
+        self.ctime.store(now, Ordering::Relaxed);
+        self.version.fetch_add(1, Ordering::AcqRel);
+    }
+
+    fn set_owner(&self, attr: &VfsNodeAttr) {
+        self.uid.store(attr.uid(), Ordering::Relaxed);
+        self.gid.store(attr.gid(), Ordering::Relaxed);
+    }
+
+    /// Applies the metadata in `attr` if the current version is
+    /// `expected_version`, and returns the new version.
+    pub fn compare_and_set_attr(
+        &self,
+        expected_version: u64,
+        attr: &VfsNodeAttr,
+        now: u64,
+    ) -> VfsResult<u64> {
+        let mut perm = self.perm.write();
+        if self.version() != expected_version {
+            return Err(VfsError::ResourceBusy);
+        }
+        *perm = attr.perm();
+        self.set_owner(attr);
+        self.ctime.store(now, Ordering::Relaxed);
+        Ok(self.version.fetch_add(1, Ordering::AcqRel) + 1)
+    }
+}
@@ -0,0 +1,75 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsResult};
+
+use crate::dir::DirNode;
+use crate::downcast_node;
+use crate::file::FileNode;
+use crate::RamFileSystem;
+
+/// An immutable RAM filesystem, created by
+/// [`RamFileSystem::seal_all`](crate::RamFileSystem::seal_all).
+///
+/// It only offers read operations. The filesystem is made read-only for
+/// good, so mutating a node obtained through [`lookup`](Self::lookup) fails
+/// with [`VfsError::ReadOnlyFilesystem`].
+pub struct SealedRamFs {
+    root: Arc<DirNode>,
+}
+
+impl SealedRamFs {
+    pub(crate) fn new(fs: RamFileSystem) -> Self {
+        // no handle is left to make it writable again
+        fs.set_readonly(true);
+        Self {
+            root: fs.root_dir_node(),
+        }
+    }
+
+    /// Looks up `path` from the root directory.
+    pub fn lookup(&self, path: &str) -> VfsResult<VfsNodeRef> {
+        self.root.clone().lookup(path)
+    }
+
+    /// Returns the attributes of the node at `path`.
+    pub fn stat(&self, path: &str) -> VfsResult<VfsNodeAttr> {
+        self.lookup(path)?.get_attr()
+    }
+
+    /// Reads the file at `path` starting from `offset` into `buf`, and
+    /// returns the number of bytes read.
+    pub fn read_at(&self, path: &str, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        self.lookup(path)?.read_at(offset, buf)
+    }
+
+    /// Returns a copy of the whole content of the file at `path`.
+    pub fn read(&self, path: &str) -> VfsResult<Vec<u8>> {
+        let node = self.lookup(path)?;
+        if node.get_attr()?.is_dir() {
+            return Err(VfsError::IsADirectory);
+        }
+        downcast_node::<FileNode>(node)
+            .ok_or(VfsError::Unsupported)?
+            .with_range(0, usize::MAX, |data| data.to_vec())
+    }
+
+    /// Returns the content of the file at `path` as a string.
+    ///
+    /// Returns [`VfsError::InvalidData`] if the content is not valid UTF-8.
+    pub fn read_to_string(&self, path: &str) -> VfsResult<String> {
+        String::from_utf8(self.read(path)?).map_err(|_| VfsError::InvalidData)
+    }
+
+    /// Reads the entries of the directory at `path`, like
+    /// [`VfsNodeOps::read_dir`].
+    pub fn read_dir(
+        &self,
+        path: &str,
+        start_idx: usize,
+        dirents: &mut [VfsDirEntry],
+    ) -> VfsResult<usize> {
+        self.lookup(path)?.read_dir(start_idx, dirents)
+    }
+}
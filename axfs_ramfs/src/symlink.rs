@@ -0,0 +1,161 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use axfs_vfs::{impl_vfs_non_dir_default, VfsError, VfsNodeAttr, VfsNodeOps, VfsResult};
+use axfs_vfs::{VfsNodePerm, VfsNodeType};
+use spin::RwLock;
+
+use crate::context::FsContext;
+use crate::meta::NodeMeta;
+
+pub(crate) type TargetFn = dyn Fn() -> VfsResult<String> + Send + Sync;
+
+enum Target {
+    Static(String),
+    /// Generated on every access.
+    Dynamic(Arc<TargetFn>),
+    /// Generated on the first access after creation or invalidation.
+    Cached(Arc<TargetFn>, RwLock<Option<String>>),
+}
+
+/// The symbolic link node in the RAM filesystem.
+///
+/// It implements [`axfs_vfs::VfsNodeOps`]. The target is stored as given, or
+/// generated by a callback for dynamic links, and is only resolved by
+/// [`DirNode::lookup_follow`](crate::DirNode::lookup_follow).
+///
+/// The callback of a dynamic link may fail, in which case the error is
+/// returned by [`target`](Self::target) and by the [`VfsNodeOps`] methods
+/// that need the target. A generated target is validated like a static one,
+/// and an invalid one is returned as such an error.
+pub struct SymlinkNode {
+    ctx: Arc<FsContext>,
+    meta: NodeMeta,
+    target: Target,
+}
+
+impl SymlinkNode {
+    pub(super) fn new(target: &str, ctx: Arc<FsContext>) -> Arc<Self> {
+        Self::with_target(Target::Static(target.into()), ctx)
+    }
+
+    pub(super) fn new_dynamic(generate: Arc<TargetFn>, ctx: Arc<FsContext>) -> Arc<Self> {
+        Self::with_target(Target::Dynamic(generate), ctx)
+    }
+
+    pub(super) fn new_dynamic_cached(generate: Arc<TargetFn>, ctx: Arc<FsContext>) -> Arc<Self> {
+        Self::with_target(Target::Cached(generate, RwLock::new(None)), ctx)
+    }
+
+    /// Creates a link like this one in the filesystem of `ctx`, sharing the
+    /// callback of a dynamic link.
+    pub(super) fn duplicate(&self, ctx: Arc<FsContext>) -> Arc<Self> {
+        match &self.target {
+            Target::Static(target) => Self::new(target, ctx),
+            Target::Dynamic(generate) => Self::new_dynamic(generate.clone(), ctx),
+            Target::Cached(generate, _) => Self::new_dynamic_cached(generate.clone(), ctx),
+        }
+    }
+
+    fn with_target(target: Target, ctx: Arc<FsContext>) -> Arc<Self> {
+        ctx.node_created(VfsNodeType::SymLink);
+        Arc::new(Self {
+            meta: NodeMeta::new(
+                ctx.alloc_ino(),
+                VfsNodePerm::from_bits_truncate(0o777),
+                ctx.now(),
+            ),
+            ctx,
+            target,
+        })
+    }
+
+    /// Returns the path the link points to.
+    ///
+    /// For a dynamic link, the target is generated by its callback, except
+    /// that a cached one returns the last generated target until
+    /// [`invalidate`](Self::invalidate) is called, even if the callback
+    /// would now return something else. A failed generation is not cached.
+    pub fn target(&self) -> VfsResult<String> {
+        match &self.target {
+            Target::Static(target) => Ok(target.clone()),
+            Target::Dynamic(generate) => self.generate(&**generate),
+            Target::Cached(generate, cache) => {
+                if let Some(target) = &*cache.read() {
+                    return Ok(target.clone());
+                }
+                let mut cache = cache.write();
+                if let Some(target) = &*cache {
+                    return Ok(target.clone());
+                }
+                Ok(cache.insert(self.generate(&**generate)?).clone())
+            }
+        }
+    }
+
+    fn generate(&self, generate: &TargetFn) -> VfsResult<String> {
+        let target = generate()?;
+        self.ctx.check_symlink_target(&target)?;
+        Ok(target)
+    }
+
+    /// Returns the length of the target in bytes, i.e. the buffer size
+    /// needed by [`readlink`](VfsNodeOps::readlink).
+    pub fn target_len(&self) -> VfsResult<usize> {
+        Ok(self.target()?.len())
+    }
+
+    /// Checks whether the target is generated by a callback.
+    pub fn is_dynamic(&self) -> bool {
+        !matches!(self.target, Target::Static(_))
+    }
+
+    /// Drops the cached target of a cached dynamic link, so the next access
+    /// generates it again. Does nothing for other links.
+    pub fn invalidate(&self) {
+        if let Target::Cached(_, cache) = &self.target {
+            *cache.write() = None;
+        }
+    }
+}
+
+impl Drop for SymlinkNode {
+    fn drop(&mut self) {
+        self.ctx.node_dropped(VfsNodeType::SymLink);
+    }
+}
+
+impl VfsNodeOps for SymlinkNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(self
+            .meta
+            .attr(VfsNodeType::SymLink, self.target_len()? as _))
+    }
+
+    /// The permissions of a symbolic link are not used, so changing them is
+    /// accepted but has no effect.
+    fn set_attr(&self, _attr: &VfsNodeAttr) -> VfsResult {
+        Ok(())
+    }
+
+    /// Copies the target into `buf` and returns its length.
+    ///
+    /// If `buf` is shorter than the target, the part that fits is copied
+    /// and [`VfsError::OutOfRange`] is returned, so a truncated target is
+    /// never mistaken for the whole one.
+    fn readlink(&self, _path: &str, buf: &mut [u8]) -> VfsResult<usize> {
+        let target = self.target()?;
+        let len = buf.len().min(target.len());
+        buf[..len].copy_from_slice(&target.as_bytes()[..len]);
+        self.meta.touch_accessed(self.ctx.now());
+        match len == target.len() {
+            true => Ok(len),
+            false => Err(VfsError::OutOfRange),
+        }
+    }
+
+    fn is_symlink(&self) -> bool {
+        true
+    }
+
+    impl_vfs_non_dir_default! {}
+}
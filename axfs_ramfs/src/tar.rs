@@ -0,0 +1,196 @@
+//! Extraction of ustar archives into a RAM filesystem.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+
+use axfs_vfs::path::canonicalize;
+use axfs_vfs::{VfsError, VfsNodeOps, VfsResult};
+
+use crate::dir::DirNode;
+
+const BLOCK_SIZE: usize = 512;
+
+/// Limits enforced while extracting a tar archive, to bound the resources an
+/// untrusted archive can consume.
+///
+/// The default imposes no limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TarLimits {
+    /// Maximum total size of the extracted file contents, in bytes.
+    pub max_total_size: usize,
+    /// Maximum number of non-directory entries.
+    pub max_files: usize,
+    /// Maximum number of components in an entry path.
+    pub max_depth: usize,
+}
+
+impl Default for TarLimits {
+    fn default() -> Self {
+        Self {
+            max_total_size: usize::MAX,
+            max_files: usize::MAX,
+            max_depth: usize::MAX,
+        }
+    }
+}
+
+enum EntryKind<'a> {
+    File(&'a [u8]),
+    Dir,
+    Symlink(&'a str),
+    Other(u8),
+}
+
+struct Entry<'a> {
+    path: String,
+    kind: EntryKind<'a>,
+}
+
+/// Iterates over the entries of a ustar archive.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn next_entry(&mut self) -> VfsResult<Option<Entry<'a>>> {
+        if self.0.len() < BLOCK_SIZE {
+            return match self.0.is_empty() {
+                true => Ok(None),
+                false => Err(VfsError::InvalidData),
+            };
+        }
+        let (header, rest) = self.0.split_at(BLOCK_SIZE);
+        if header.iter().all(|&b| b == 0) {
+            return Ok(None); // end-of-archive marker
+        }
+        check_checksum(header)?;
+
+        let size = parse_octal(&header[124..136])?;
+        let data_len = size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+        if rest.len() < data_len {
+            return Err(VfsError::InvalidData);
+        }
+        let (data, rest) = rest.split_at(data_len);
+        self.0 = rest;
+
+        let name = parse_str(&header[0..100])?;
+        let path = match &header[257..262] == b"ustar" {
+            true => match parse_str(&header[345..500])? {
+                "" => name.into(),
+                prefix => format!("{prefix}/{name}"),
+            },
+            false => name.into(),
+        };
+        let kind = match header[156] {
+            b'0' | b'\0' => EntryKind::File(&data[..size]),
+            b'5' => EntryKind::Dir,
+            b'2' => EntryKind::Symlink(parse_str(&header[157..257])?),
+            ty => EntryKind::Other(ty),
+        };
+        Ok(Some(Entry { path, kind }))
+    }
+}
+
+fn check_checksum(header: &[u8]) -> VfsResult {
+    let expected = parse_octal(&header[148..156])?;
+    let sum: usize = header
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| if (148..156).contains(&i) { b' ' } else { b } as usize)
+        .sum();
+    match sum == expected {
+        true => Ok(()),
+        false => Err(VfsError::InvalidData),
+    }
+}
+
+fn parse_str(field: &[u8]) -> VfsResult<&str> {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    core::str::from_utf8(&field[..len]).map_err(|_| VfsError::InvalidData)
+}
+
+fn parse_octal(field: &[u8]) -> VfsResult<usize> {
+    let digits = parse_str(field)?.trim_matches(|c| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    usize::from_str_radix(digits, 8).map_err(|_| VfsError::InvalidData)
+}
+
+/// Extracts the ustar archive `data` into `root`.
+///
+/// Entries of types other than directories, regular files and symlinks are
+/// skipped with a warning.
+///
+/// Exceeding [`TarLimits::max_total_size`] or [`TarLimits::max_files`] fails
+/// with [`VfsError::StorageFull`], and exceeding [`TarLimits::max_depth`]
+/// with [`VfsError::NameTooLong`]. Limits are checked before the content of
+/// an entry is copied.
+/// Removes the entry `name` of `dir` for a later archive entry to replace.
+///
+/// Fails with [`VfsError::InvalidData`] if it is a directory, which an entry
+/// of another type must not replace, even if it is empty.
+fn remove_replaced(dir: &Arc<DirNode>, name: &str) -> VfsResult {
+    let node = dir.clone().lookup(name)?;
+    if node.as_any().is::<DirNode>() {
+        return Err(VfsError::InvalidData);
+    }
+    dir.remove_node(name)
+}
+
+pub(crate) fn extract(root: &Arc<DirNode>, data: &[u8], limits: &TarLimits) -> VfsResult {
+    let mut reader = Reader(data);
+    let mut total_size = 0usize;
+    let mut files = 0usize;
+    while let Some(entry) = reader.next_entry()? {
+        let path = canonicalize(&format!("/{}", entry.path));
+        let path = path.trim_start_matches('/');
+        if path.is_empty() {
+            continue;
+        }
+        if path.split('/').count() > limits.max_depth {
+            return Err(VfsError::NameTooLong);
+        }
+        if !matches!(entry.kind, EntryKind::Dir) {
+            files += 1;
+            if files > limits.max_files {
+                return Err(VfsError::StorageFull);
+            }
+        }
+        match entry.kind {
+            EntryKind::Dir => {
+                root.create_dir_all(path)?;
+            }
+            EntryKind::File(content) => {
+                total_size = total_size.saturating_add(content.len());
+                if total_size > limits.max_total_size {
+                    return Err(VfsError::StorageFull);
+                }
+                let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+                let mut content = Some(content.to_vec());
+                let dir = root.create_dir_all(dir)?;
+                let file = match dir.get_or_init_file(name, || content.take().unwrap()) {
+                    Err(VfsError::AlreadyExists) => {
+                        remove_replaced(&dir, name)?; // not a file, e.g. an earlier symlink
+                        dir.get_or_init_file(name, || content.take().unwrap())?
+                    }
+                    res => res?,
+                };
+                if let Some(content) = content {
+                    file.give_contents(content)?; // a later entry replaces an earlier one
+                }
+            }
+            EntryKind::Symlink(target) => {
+                let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+                let dir = root.create_dir_all(dir)?;
+                if let Err(VfsError::AlreadyExists) = dir.create_symlink(name, target) {
+                    remove_replaced(&dir, name)?; // a later entry replaces an earlier one
+                    dir.create_symlink(name, target)?;
+                }
+            }
+            EntryKind::Other(ty) => {
+                log::warn!("tar: skipping {path} of unsupported type {:?}", ty as char);
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,6 +1,8 @@
 use std::sync::Arc;
 
-use axfs_vfs::{VfsError, VfsNodeType, VfsResult};
+use axfs_vfs::{
+    VfsDirEntry, VfsError, VfsNodeAttr, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsResult,
+};
 
 use crate::*;
 
@@ -134,3 +136,3262 @@ fn test_ramfs() {
     assert_eq!(root.remove("./foo"), Ok(()));
     assert!(ramfs.root_dir_node().get_entries().is_empty());
 }
+
+#[test]
+fn test_get_or_init_file() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let calls = AtomicUsize::new(0);
+    let init = || {
+        calls.fetch_add(1, Ordering::SeqCst);
+        b"init".to_vec()
+    };
+
+    let (f1, f2) = std::thread::scope(|s| {
+        let t1 = s.spawn(|| root.get_or_init_file("once", init).unwrap());
+        let t2 = s.spawn(|| root.get_or_init_file("once", init).unwrap());
+        (t1.join().unwrap(), t2.join().unwrap())
+    });
+    assert!(Arc::ptr_eq(&f1, &f2));
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    let mut buf = [0; 8];
+    assert_eq!(f1.read_at(0, &mut buf).unwrap(), 4);
+    assert_eq!(&buf[..4], b"init");
+
+    root.create("dir", VfsNodeType::Dir).unwrap();
+    assert_eq!(
+        root.get_or_init_file("dir", Vec::new).err(),
+        Some(VfsError::AlreadyExists)
+    );
+}
+
+#[test]
+fn test_file_with_range() {
+    let ramfs = RamFileSystem::new();
+    let file = ramfs
+        .root_dir_node()
+        .get_or_init_file("f", || b"0123456789".to_vec())
+        .unwrap();
+
+    assert_eq!(file.with_range(2, 3, |s| s.to_vec()).unwrap(), b"234");
+    assert_eq!(file.with_range(8, 100, |s| s.to_vec()).unwrap(), b"89");
+    assert_eq!(file.with_range(10, 4, |s| s.len()).unwrap(), 0);
+    assert_eq!(
+        file.with_range(11, 1, |s| s.len()).err(),
+        Some(VfsError::InvalidInput)
+    );
+}
+
+#[test]
+fn test_rename() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("a", VfsNodeType::Dir).unwrap();
+    root.create("a/sub", VfsNodeType::Dir).unwrap();
+    root.create("b", VfsNodeType::Dir).unwrap();
+    root.create("a/f1", VfsNodeType::File).unwrap();
+    root.create("b/f2", VfsNodeType::File).unwrap();
+    root.clone()
+        .lookup("a/f1")
+        .unwrap()
+        .write_at(0, b"f1")
+        .unwrap();
+
+    // rename within a directory, and onto itself
+    ramfs.rename("a/f1", "a/f3").unwrap();
+    ramfs.rename("/a/f3", "a//f3").unwrap();
+    assert_eq!(root.clone().lookup("a/f1").err(), Some(VfsError::NotFound));
+
+    // move to another directory replacing an existing file
+    ramfs.rename("a/f3", "/b/f2").unwrap();
+    let mut buf = [0; 4];
+    let f2 = root.clone().lookup("b/f2").unwrap();
+    assert_eq!(f2.read_at(0, &mut buf).unwrap(), 2);
+    assert_eq!(&buf[..2], b"f1");
+    let entries = root.clone().lookup("b").unwrap();
+    let entries = entries.as_any().downcast_ref::<DirNode>().unwrap();
+    assert_eq!(entries.get_entries(), ["f2"]);
+
+    // move a directory and fix up its parent
+    ramfs.rename("a/sub", "b/sub").unwrap();
+    let sub = root.clone().lookup("b/sub").unwrap();
+    assert!(Arc::ptr_eq(
+        &sub.parent().unwrap(),
+        &root.clone().lookup("b").unwrap()
+    ));
+    assert!(Arc::ptr_eq(
+        &root.clone().lookup("b/sub/..").unwrap(),
+        &root.clone().lookup("b").unwrap()
+    ));
+
+    // invalid moves
+    assert_eq!(ramfs.rename("b", "b/sub/b"), Err(VfsError::InvalidInput));
+    assert_eq!(ramfs.rename("b", "b"), Ok(()));
+    assert_eq!(ramfs.rename("b/f2", "b/sub"), Err(VfsError::IsADirectory));
+    assert_eq!(ramfs.rename("a", "b/f2"), Err(VfsError::NotADirectory));
+    assert_eq!(ramfs.rename("a", "b"), Err(VfsError::DirectoryNotEmpty));
+    assert_eq!(ramfs.rename("a/none", "b/none"), Err(VfsError::NotFound));
+    assert_eq!(ramfs.rename("b/f2", "b/f2/x"), Err(VfsError::NotADirectory));
+    assert_eq!(ramfs.rename("b/f2", ".."), Err(VfsError::InvalidInput));
+
+    // a directory may replace an empty one
+    ramfs.rename("b/sub", "a").unwrap();
+    assert!(root.clone().lookup("b/sub").is_err());
+    assert!(Arc::ptr_eq(&root.clone().lookup("a/..").unwrap(), &root));
+}
+
+#[test]
+fn test_compare_and_set_attr() {
+    let ramfs = RamFileSystem::new();
+    let file = ramfs
+        .root_dir_node()
+        .get_or_init_file("f", Vec::new)
+        .unwrap();
+    let mut attr = file.get_attr().unwrap();
+    let version = file.version();
+
+    attr.set_perm(VfsNodePerm::from_bits_truncate(0o600));
+    let new_version = file.compare_and_set_attr(version, attr).unwrap();
+    assert_eq!(new_version, version + 1);
+    assert_eq!(file.get_attr().unwrap().perm().bits(), 0o600);
+
+    // a stale version is rejected and leaves the metadata untouched
+    attr.set_perm(VfsNodePerm::from_bits_truncate(0o644));
+    assert_eq!(
+        file.compare_and_set_attr(version, attr).err(),
+        Some(VfsError::ResourceBusy)
+    );
+    assert_eq!(file.get_attr().unwrap().perm().bits(), 0o600);
+    assert_eq!(file.version(), new_version);
+}
+
+#[test]
+fn test_compare_and_set_attr_readonly() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let file = root.get_or_init_file("f", Vec::new).unwrap();
+    let mut attr = file.get_attr().unwrap();
+    attr.set_perm(VfsNodePerm::from_bits_truncate(0o600));
+
+    ramfs.set_readonly(true);
+    let denied = Err(VfsError::ReadOnlyFilesystem);
+    assert_eq!(file.compare_and_set_attr(file.version(), attr), denied);
+    let root_attr = root.get_attr().unwrap();
+    assert_eq!(root.compare_and_set_attr(root.version(), root_attr), denied);
+    assert_eq!(file.get_attr().unwrap().perm().bits(), 0o666);
+
+    ramfs.set_readonly(false);
+    root.set_subtree_readonly(true);
+    assert_eq!(file.compare_and_set_attr(file.version(), attr), denied);
+}
+
+#[test]
+fn test_version_bumps() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let file = root.get_or_init_file("f", Vec::new).unwrap();
+    let version = file.version();
+    file.write_at(0, b"data").unwrap();
+    assert_eq!(file.version(), version + 1);
+    file.set_xattr("user.k", b"v").unwrap();
+    file.remove_xattr("user.k").unwrap();
+    assert_eq!(file.version(), version + 3);
+    let root_version = root.version();
+    root.create("g", VfsNodeType::File).unwrap();
+    assert!(root.version() > root_version);
+
+    // reading only updates the access time
+    let version = file.version();
+    file.read_at(0, &mut [0; 4]).unwrap();
+    assert_eq!(file.version(), version);
+
+    // a version read before a content change is stale
+    let attr = file.get_attr().unwrap();
+    file.write_at(0, b"x").unwrap();
+    assert_eq!(
+        file.compare_and_set_attr(version, attr).err(),
+        Some(VfsError::ResourceBusy)
+    );
+}
+
+#[test]
+fn test_encode_listing() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("dir", VfsNodeType::Dir).unwrap();
+    root.get_or_init_file("file", || b"hello".to_vec()).unwrap();
+
+    let data = root.encode_listing().unwrap();
+    let entries = decode_listing(&data).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(
+        entries[0],
+        ListingEntry {
+            name: "dir".into(),
+            ty: VfsNodeType::Dir,
+            size: 4096,
+            ino: root
+                .clone()
+                .lookup("dir")
+                .ok()
+                .unwrap()
+                .get_attr()
+                .unwrap()
+                .ino(),
+        }
+    );
+    assert_eq!(entries[1].name, "file");
+    assert_eq!(entries[1].ty, VfsNodeType::File);
+    assert_eq!(entries[1].size, 5);
+
+    assert_eq!(
+        decode_listing(&data[..data.len() - 1]).err(),
+        Some(VfsError::InvalidData)
+    );
+    assert_eq!(
+        decode_listing(b"RFSL\x02\0\0\0\0").err(),
+        Some(VfsError::InvalidData)
+    );
+}
+
+#[test]
+fn test_truncate_subtree() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("logs", VfsNodeType::Dir).unwrap();
+    root.create("logs/old", VfsNodeType::Dir).unwrap();
+    for path in ["logs/a", "logs/old/b", "keep"] {
+        root.create(path, VfsNodeType::File).unwrap();
+        root.clone()
+            .lookup(path)
+            .unwrap()
+            .write_at(0, b"data")
+            .unwrap();
+    }
+
+    assert_eq!(ramfs.truncate_subtree("logs", 0), Ok(2));
+    for path in ["logs/a", "logs/old/b"] {
+        assert_eq!(
+            root.clone()
+                .lookup(path)
+                .unwrap()
+                .get_attr()
+                .unwrap()
+                .size(),
+            0
+        );
+    }
+    assert_eq!(
+        root.clone()
+            .lookup("keep")
+            .unwrap()
+            .get_attr()
+            .unwrap()
+            .size(),
+        4
+    );
+    assert!(root
+        .clone()
+        .lookup("logs/old")
+        .unwrap()
+        .get_attr()
+        .unwrap()
+        .is_dir());
+    assert_eq!(
+        ramfs.truncate_subtree("keep", 0),
+        Err(VfsError::NotADirectory)
+    );
+}
+
+#[test]
+fn test_user_data() {
+    struct Handle(u32);
+
+    let ramfs = RamFileSystem::new();
+    let file = ramfs
+        .root_dir_node()
+        .get_or_init_file("f", Vec::new)
+        .unwrap();
+    assert!(file.user_data().is_none());
+
+    file.set_user_data(Arc::new(Handle(42)));
+    let data = file.user_data().unwrap().downcast::<Handle>().unwrap();
+    assert_eq!(data.0, 42);
+    assert!(file.user_data().unwrap().downcast::<u32>().is_err());
+
+    ramfs.root_dir_node().set_user_data(Arc::new(7u32));
+    let data = ramfs.root_dir_node().user_data().unwrap();
+    assert_eq!(*data.downcast::<u32>().unwrap(), 7);
+}
+
+#[test]
+fn test_lookup_trace() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("a", VfsNodeType::Dir).unwrap();
+    root.create("a/b", VfsNodeType::Dir).unwrap();
+    root.create("a/b/c", VfsNodeType::File).unwrap();
+
+    let chain = ramfs.root_dir_node().lookup_trace("/a//b/c").ok().unwrap();
+    let names: Vec<_> = chain.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["a", "b", "c"]);
+    assert!(Arc::ptr_eq(
+        &chain[1].1,
+        &root.clone().lookup("a/b").unwrap()
+    ));
+    assert!(Arc::ptr_eq(
+        &chain[2].1,
+        &root.clone().lookup("a/b/c").unwrap()
+    ));
+
+    let (chain, err) = ramfs.root_dir_node().lookup_trace("a/x/c").err().unwrap();
+    assert_eq!(err, VfsError::NotFound);
+    assert_eq!(chain.len(), 1);
+    assert_eq!(chain[0].0, "a");
+
+    let (chain, err) = ramfs.root_dir_node().lookup_trace("a/b/c/d").err().unwrap();
+    assert_eq!(err, VfsError::NotADirectory);
+    assert_eq!(chain.len(), 3);
+}
+
+#[test]
+fn test_allowed_child_types() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("leaf", VfsNodeType::Dir).unwrap();
+    root.create("f", VfsNodeType::File).unwrap();
+    root.create("d", VfsNodeType::Dir).unwrap();
+
+    let leaf = root.clone().lookup("leaf").unwrap();
+    let leaf = leaf.as_any().downcast_ref::<DirNode>().unwrap();
+    leaf.set_allowed_child_types(&[VfsNodeType::File]);
+    assert_eq!(
+        leaf.create_node("sub", VfsNodeType::Dir),
+        Err(VfsError::PermissionDenied)
+    );
+    assert_eq!(leaf.create_node("file", VfsNodeType::File), Ok(()));
+    assert_eq!(
+        leaf.add_node("dir", root.clone().lookup("d").unwrap()),
+        Err(VfsError::PermissionDenied)
+    );
+    assert_eq!(ramfs.rename("d", "leaf/d"), Err(VfsError::PermissionDenied));
+    assert_eq!(ramfs.rename("f", "leaf/f"), Ok(()));
+
+    leaf.allow_all_child_types();
+    assert_eq!(leaf.create_node("sub", VfsNodeType::Dir), Ok(()));
+}
+
+#[test]
+fn test_auto_mkdir() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    assert_eq!(
+        root.create("a/b/c", VfsNodeType::File),
+        Err(VfsError::NotFound)
+    );
+    assert_eq!(root.symlink("c", "s/t/l"), Err(VfsError::NotFound));
+
+    ramfs.set_auto_mkdir(true);
+    root.create("a/b/c", VfsNodeType::File).unwrap();
+    assert!(root
+        .clone()
+        .lookup("a/b")
+        .unwrap()
+        .get_attr()
+        .unwrap()
+        .is_dir());
+    assert!(root
+        .clone()
+        .lookup("a/b/c")
+        .unwrap()
+        .get_attr()
+        .unwrap()
+        .is_file());
+    root.create("/a/d//e", VfsNodeType::Dir).unwrap();
+    assert!(root
+        .clone()
+        .lookup("a/d/e")
+        .unwrap()
+        .get_attr()
+        .unwrap()
+        .is_dir());
+    assert_eq!(
+        root.create("a/b/c/d", VfsNodeType::File),
+        Err(VfsError::NotADirectory)
+    );
+
+    root.symlink("../a/b/c", "s/t/l").unwrap();
+    assert!(root
+        .clone()
+        .lookup("s/t")
+        .unwrap()
+        .get_attr()
+        .unwrap()
+        .is_dir());
+    let mut buf = [0; 16];
+    let len = root.readlink("s/t/l", &mut buf).unwrap();
+    assert_eq!(&buf[..len], b"../a/b/c");
+    assert_eq!(root.symlink("c", "a/b/c/l"), Err(VfsError::NotADirectory));
+}
+
+#[test]
+fn test_relative_path() {
+    let ramfs = RamFileSystem::new();
+    assert_eq!(
+        ramfs.relative_path("/a/b", "/a/c/d").as_deref(),
+        Some("../c/d")
+    );
+    assert_eq!(ramfs.relative_path("/a/b/", "/a/b/c").as_deref(), Some("c"));
+    assert_eq!(ramfs.relative_path("/a/./b", "/a/b").as_deref(), Some("."));
+    assert_eq!(
+        ramfs.relative_path("/a/b/c", "/").as_deref(),
+        Some("../../..")
+    );
+    assert_eq!(
+        ramfs.relative_path("/x/../a", "/b").as_deref(),
+        Some("../b")
+    );
+    assert_eq!(ramfs.relative_path("a/b", "/a"), None);
+    assert_eq!(ramfs.relative_path("/a", ""), None);
+}
+
+#[test]
+fn test_dir_nlink() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    assert_eq!(root.get_attr().unwrap().nlink(), 2);
+    root.create("d1", VfsNodeType::Dir).unwrap();
+    root.create("f", VfsNodeType::File).unwrap();
+    root.create("d2", VfsNodeType::Dir).unwrap();
+    assert_eq!(root.get_attr().unwrap().nlink(), 4);
+    assert_eq!(
+        root.clone()
+            .lookup("f")
+            .unwrap()
+            .get_attr()
+            .unwrap()
+            .nlink(),
+        1
+    );
+    root.remove("d1").unwrap();
+    assert_eq!(root.get_attr().unwrap().nlink(), 3);
+}
+
+#[test]
+fn test_stat_many() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("d", VfsNodeType::Dir).unwrap();
+    root.create("d/f", VfsNodeType::File).unwrap();
+
+    let attrs = ramfs.stat_many(&["d", "missing", "/d/f", "d/f/x"]);
+    assert_eq!(attrs.len(), 4);
+    assert!(attrs[0].as_ref().unwrap().is_dir());
+    assert_eq!(attrs[1].as_ref().err(), Some(&VfsError::NotFound));
+    assert!(attrs[2].as_ref().unwrap().is_file());
+    assert_eq!(attrs[3].as_ref().err(), Some(&VfsError::NotADirectory));
+}
+
+#[test]
+fn test_subtree_readonly() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    ramfs.set_auto_mkdir(true);
+    root.create("usr/lib/f", VfsNodeType::File).unwrap();
+    root.create("home/f", VfsNodeType::File).unwrap();
+    let usr = root.clone().lookup("usr").unwrap();
+    let usr = usr.as_any().downcast_ref::<DirNode>().unwrap();
+    usr.set_subtree_readonly(true);
+
+    let ro_file = root.clone().lookup("usr/lib/f").unwrap();
+    assert_eq!(ro_file.write_at(0, b"x"), Err(VfsError::ReadOnlyFilesystem));
+    assert_eq!(ro_file.truncate(1), Err(VfsError::ReadOnlyFilesystem));
+    assert_eq!(
+        root.create("usr/lib/g", VfsNodeType::File),
+        Err(VfsError::ReadOnlyFilesystem)
+    );
+    assert_eq!(root.remove("usr/lib/f"), Err(VfsError::ReadOnlyFilesystem));
+    assert_eq!(
+        ramfs.rename("home/f", "usr/f"),
+        Err(VfsError::ReadOnlyFilesystem)
+    );
+    let mut buf = [0; 1];
+    assert_eq!(ro_file.read_at(0, &mut buf), Ok(0));
+
+    let rw_file = root.clone().lookup("home/f").unwrap();
+    assert_eq!(rw_file.write_at(0, b"x"), Ok(1));
+    assert_eq!(root.create("home/g", VfsNodeType::File), Ok(()));
+
+    // files moved out of the read-only subtree become writable
+    usr.set_subtree_readonly(false);
+    ramfs.rename("usr/lib/f", "home/h").unwrap();
+    usr.set_subtree_readonly(true);
+    assert_eq!(ro_file.write_at(0, b"x"), Ok(1));
+}
+
+#[test]
+fn test_resolve_with_parent() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    ramfs.set_auto_mkdir(true);
+    root.create("a/b/c", VfsNodeType::File).unwrap();
+
+    let (parent, node) = ramfs.resolve_with_parent("/a/b/c").unwrap();
+    assert!(Arc::ptr_eq(&node, &root.clone().lookup("a/b/c").unwrap()));
+    let parent: VfsNodeRef = parent.unwrap();
+    assert!(Arc::ptr_eq(&parent, &root.clone().lookup("a/b").unwrap()));
+
+    let (parent, node) = ramfs.resolve_with_parent("a/./b/../b").unwrap();
+    let parent: VfsNodeRef = parent.unwrap();
+    assert!(Arc::ptr_eq(&parent, &root.clone().lookup("a").unwrap()));
+    assert!(Arc::ptr_eq(&node, &root.clone().lookup("a/b").unwrap()));
+
+    let (parent, node) = ramfs.resolve_with_parent("/").unwrap();
+    assert!(parent.is_none());
+    assert!(Arc::ptr_eq(&node, &root));
+    assert_eq!(
+        ramfs.resolve_with_parent("a/x").err(),
+        Some(VfsError::NotFound)
+    );
+}
+
+#[test]
+fn test_replace_contents() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    const OLD: [u8; 4096] = [b'a'; 4096];
+    const NEW: [u8; 1024] = [b'b'; 1024];
+
+    let ramfs = RamFileSystem::new();
+    let file = ramfs
+        .root_dir_node()
+        .get_or_init_file("config", || OLD.to_vec())
+        .unwrap();
+    let done = AtomicBool::new(false);
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let mut buf = [0; 8192];
+            while !done.load(Ordering::SeqCst) {
+                let n = file.read_at(0, &mut buf).unwrap();
+                assert!(buf[..n] == OLD || buf[..n] == NEW);
+            }
+        });
+        for i in 0..1000 {
+            file.replace_contents(if i % 2 == 0 { &NEW } else { &OLD })
+                .unwrap();
+        }
+        done.store(true, Ordering::SeqCst);
+    });
+    assert_eq!(file.get_attr().unwrap().size(), OLD.len() as u64);
+}
+
+#[test]
+fn test_gen_dir() {
+    use axfs_vfs::VfsDirEntry;
+    use std::sync::Mutex;
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let task = root
+        .get_or_init_file("task", || b"running".to_vec())
+        .unwrap();
+    let tasks = Arc::new(Mutex::new(vec![String::from("1")]));
+
+    let (list_tasks, resolve_tasks) = (tasks.clone(), tasks.clone());
+    let gen = GenDirNode::new(
+        move || {
+            let tasks = list_tasks.lock().unwrap();
+            tasks
+                .iter()
+                .map(|t| (t.clone(), VfsNodeType::File))
+                .collect()
+        },
+        move |name| {
+            let found = resolve_tasks.lock().unwrap().iter().any(|t| t == name);
+            found.then(|| task.clone() as VfsNodeRef)
+        },
+    );
+    let read_names = |dir: &VfsNodeRef| {
+        let mut dirents: Vec<_> = (0..8).map(|_| VfsDirEntry::default()).collect();
+        let n = dir.read_dir(0, &mut dirents).unwrap();
+        dirents[..n]
+            .iter()
+            .map(|e| String::from_utf8(e.name_as_bytes().to_vec()).unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    root.add_node("proc", Arc::new(gen)).unwrap();
+    let proc = root.clone().lookup("proc").unwrap();
+    assert_eq!(read_names(&proc), [".", "..", "1"]);
+    let up = proc.clone().lookup("..").unwrap();
+    assert!(Arc::ptr_eq(&up, &(root.clone() as VfsNodeRef)));
+    assert!(root.clone().lookup("proc/../task").is_ok());
+    let node = root.clone().lookup("proc/1").unwrap();
+    assert_eq!(node.get_attr().unwrap().size(), 7);
+    assert_eq!(
+        root.clone().lookup("proc/2").err(),
+        Some(VfsError::NotFound)
+    );
+    assert_eq!(
+        proc.create("2", VfsNodeType::File),
+        Err(VfsError::PermissionDenied)
+    );
+    assert_eq!(proc.remove("1"), Err(VfsError::PermissionDenied));
+
+    tasks.lock().unwrap().push("2".into());
+    assert_eq!(read_names(&proc), [".", "..", "1", "2"]);
+    assert!(root.clone().lookup("proc/2").is_ok());
+}
+
+#[test]
+fn test_take_give_contents() {
+    let ramfs = RamFileSystem::new();
+    let file = ramfs
+        .root_dir_node()
+        .get_or_init_file("f", || b"payload".to_vec())
+        .unwrap();
+
+    let data = file.take_contents().unwrap();
+    assert_eq!(data, b"payload");
+    assert_eq!(file.get_attr().unwrap().size(), 0);
+    assert!(file.take_contents().unwrap().is_empty());
+
+    file.give_contents(data).unwrap();
+    let mut buf = [0; 16];
+    assert_eq!(file.read_at(0, &mut buf).unwrap(), 7);
+    assert_eq!(&buf[..7], b"payload");
+}
+
+#[test]
+fn test_lookup_detailed() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    ramfs.set_auto_mkdir(true);
+    root.create("a/b/f", VfsNodeType::File).unwrap();
+
+    let node = root.clone().lookup_detailed("/a/b/f").unwrap();
+    assert!(node.get_attr().unwrap().is_file());
+
+    let err = root.clone().lookup_detailed("a//x/f").err().unwrap();
+    assert_eq!(
+        err,
+        LookupError {
+            index: 1,
+            component: "x".into(),
+            reason: LookupFailure::NotFound,
+        }
+    );
+    assert_eq!(VfsError::from(err), VfsError::NotFound);
+
+    let err = root.clone().lookup_detailed("a/b/f/g").err().unwrap();
+    assert_eq!((err.index, err.component.as_str()), (3, "g"));
+    assert_eq!(err.reason, LookupFailure::NotADirectory);
+    assert_eq!(VfsError::from(err), VfsError::NotADirectory);
+
+    let err = root.clone().lookup_detailed("a/../..").err().unwrap();
+    assert_eq!((err.index, err.component.as_str()), (2, ".."));
+    assert_eq!(err.reason, LookupFailure::NoParent);
+}
+
+#[test]
+fn test_from_map() {
+    use std::collections::BTreeMap;
+
+    let mut files = BTreeMap::new();
+    files.insert("/etc/hosts".into(), b"127.0.0.1".to_vec());
+    files.insert("/etc/conf.d/net".into(), b"dhcp".to_vec());
+    files.insert("/readme".into(), Vec::new());
+    files.insert("/var/empty/".into(), Vec::new());
+    let ramfs = RamFileSystem::from_map(files).unwrap();
+
+    let root = ramfs.root_dir();
+    let mut buf = [0; 16];
+    let node = root.clone().lookup("etc/hosts").unwrap();
+    assert_eq!(node.read_at(0, &mut buf).unwrap(), 9);
+    assert_eq!(&buf[..9], b"127.0.0.1");
+    let node = root.clone().lookup("etc/conf.d/net").unwrap();
+    assert_eq!(node.read_at(0, &mut buf).unwrap(), 4);
+    assert!(root
+        .clone()
+        .lookup("readme")
+        .unwrap()
+        .get_attr()
+        .unwrap()
+        .is_file());
+    assert!(root
+        .clone()
+        .lookup("var/empty")
+        .unwrap()
+        .get_attr()
+        .unwrap()
+        .is_dir());
+    assert_eq!(
+        ramfs.root_dir_node().get_entries(),
+        ["etc", "readme", "var"]
+    );
+
+    let mut files = BTreeMap::new();
+    files.insert("/a".into(), Vec::new());
+    files.insert("/a/b".into(), Vec::new());
+    assert_eq!(
+        RamFileSystem::from_map(files).err(),
+        Some(VfsError::NotADirectory)
+    );
+}
+
+#[test]
+fn test_to_map() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("d", VfsNodeType::Dir).unwrap();
+    root.create("d/e", VfsNodeType::Dir).unwrap();
+    root.create("d/f", VfsNodeType::File).unwrap();
+    root.create("g", VfsNodeType::File).unwrap();
+    root.symlink("d/f", "l").unwrap();
+    root.clone()
+        .lookup("d/f")
+        .unwrap()
+        .write_at(0, b"abc")
+        .unwrap();
+
+    let map = ramfs.to_map();
+    let expected: Vec<(&str, &[u8])> = vec![
+        ("/d/", b""),
+        ("/d/e/", b""),
+        ("/d/f", b"abc"),
+        ("/g", b""),
+        ("/l//", b"d/f"),
+    ];
+    let map_ref: Vec<_> = map
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_slice()))
+        .collect();
+    assert_eq!(map_ref, expected);
+
+    // round trip through from_map
+    assert_eq!(RamFileSystem::from_map(map.clone()).unwrap().to_map(), map);
+}
+
+#[test]
+fn test_access_checker() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("f", VfsNodeType::File).unwrap();
+    root.create("secret", VfsNodeType::Dir).unwrap();
+    let f = root.clone().lookup("f").ok().unwrap();
+    f.write_at(0, b"hi").unwrap();
+
+    let secret = ramfs.root_dir_node().lookup("secret").ok().unwrap();
+    ramfs.set_access_checker(move |node, mode| match mode {
+        AccessMode::Write => false,
+        AccessMode::Lookup => !Arc::ptr_eq(node, &secret),
+        AccessMode::Read => true,
+    });
+    assert_eq!(f.write_at(0, b"x").err(), Some(VfsError::PermissionDenied));
+    assert_eq!(f.truncate(0).err(), Some(VfsError::PermissionDenied));
+    let mut buf = [0; 4];
+    assert_eq!(f.read_at(0, &mut buf), Ok(2));
+    assert_eq!(&buf[..2], b"hi");
+    assert_eq!(
+        root.clone().lookup("secret/x").err(),
+        Some(VfsError::PermissionDenied)
+    );
+
+    ramfs.clear_access_checker();
+    assert_eq!(f.write_at(0, b"x"), Ok(1));
+}
+
+#[test]
+fn test_access_checker_content() {
+    let ramfs = RamFileSystem::new();
+    let file = ramfs.root_dir_node().create_file_with("f", b"hi").unwrap();
+    ramfs.set_access_checker(|_, mode| mode == AccessMode::Lookup);
+
+    let denied = Err(VfsError::PermissionDenied);
+    assert_eq!(file.with_range(0, 2, <[u8]>::to_vec), denied);
+    assert_eq!(file.with_bytes(<[u8]>::to_vec), denied);
+    assert_eq!(file.replace_contents(b"x"), Err(VfsError::PermissionDenied));
+    assert_eq!(file.take_contents(), denied);
+    assert_eq!(
+        file.give_contents(b"x".to_vec()),
+        Err(VfsError::PermissionDenied)
+    );
+    // copying the whole tree is not subject to the checker
+    assert_eq!(ramfs.to_map()["/f"], b"hi");
+
+    ramfs.clear_access_checker();
+    assert_eq!(file.with_bytes(<[u8]>::to_vec), Ok(b"hi".to_vec()));
+}
+
+#[test]
+fn test_remove_removable() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_node("a", VfsNodeType::File).unwrap();
+    root.create_node("empty", VfsNodeType::Dir).unwrap();
+    root.create_node("full", VfsNodeType::Dir).unwrap();
+    ramfs
+        .root_dir()
+        .create("full/x", VfsNodeType::File)
+        .unwrap();
+
+    let report = root.remove_removable(&["a", "full", "missing", "empty"]);
+    assert_eq!(report.removed, ["a", "empty"]);
+    assert_eq!(
+        report.skipped,
+        [
+            ("full".into(), VfsError::DirectoryNotEmpty),
+            ("missing".into(), VfsError::NotFound),
+        ]
+    );
+    assert!(root.exist("full"));
+    assert!(!root.exist("a") && !root.exist("empty"));
+}
+
+#[test]
+fn test_peak_memory_usage() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("small", VfsNodeType::File).unwrap();
+    root.create("big", VfsNodeType::File).unwrap();
+    let small = root.clone().lookup("small").ok().unwrap();
+    small.write_at(0, &[1; 100]).unwrap();
+    let big = root.clone().lookup("big").ok().unwrap();
+    big.write_at(0, &[2; 4096]).unwrap();
+    big.write_at(1000, &[3; 16]).unwrap();
+    assert_eq!(ramfs.memory_usage(), 4196);
+    drop(big);
+    root.remove("big").unwrap();
+    assert_eq!(ramfs.memory_usage(), 100);
+    assert_eq!(ramfs.peak_memory_usage(), 4196);
+
+    ramfs.reset_peak();
+    assert_eq!(ramfs.peak_memory_usage(), 100);
+    small.truncate(10).unwrap();
+    assert_eq!(ramfs.memory_usage(), 10);
+    assert_eq!(ramfs.peak_memory_usage(), 100);
+}
+
+#[test]
+fn test_link_exclusive() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_node("f", VfsNodeType::File).unwrap();
+    root.create_node("d", VfsNodeType::Dir).unwrap();
+    let f = root.clone().lookup("f").ok().unwrap();
+    let d = root.clone().lookup("d").ok().unwrap();
+
+    root.link_exclusive("f1", f.clone()).unwrap();
+    root.link_exclusive("f2", f.clone()).unwrap();
+    assert_eq!(
+        root.link_exclusive("f1", f.clone()),
+        Err(VfsError::AlreadyExists)
+    );
+    assert_eq!(
+        root.link_exclusive("d1", d),
+        Err(VfsError::PermissionDenied)
+    );
+    assert!(!root.exist("d1"));
+
+    f.write_at(0, b"shared").unwrap();
+    let mut buf = [0; 8];
+    let f2 = root.clone().lookup("f2").ok().unwrap();
+    assert_eq!(f2.read_at(0, &mut buf), Ok(6));
+    assert_eq!(&buf[..6], b"shared");
+}
+
+#[test]
+fn test_find_by_ino() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("a", VfsNodeType::Dir).unwrap();
+    root.create("a/f", VfsNodeType::File).unwrap();
+    let f = root.clone().lookup("a/f").ok().unwrap();
+    let a = root.clone().lookup("a").ok().unwrap();
+
+    let ino = f.get_attr().unwrap().ino();
+    assert_ne!(ino, a.get_attr().unwrap().ino());
+    assert!(Arc::ptr_eq(&ramfs.find_by_ino(ino).unwrap(), &f));
+    let root_ino = root.get_attr().unwrap().ino();
+    assert!(Arc::ptr_eq(&ramfs.find_by_ino(root_ino).unwrap(), &root));
+    assert!(ramfs.find_by_ino(u64::MAX).is_none());
+}
+
+#[test]
+fn test_rotate() {
+    let ramfs = Arc::new(RamFileSystem::new());
+    let root = ramfs.root_dir();
+    root.create("log", VfsNodeType::Dir).unwrap();
+    root.create("log/app", VfsNodeType::File).unwrap();
+    let old = root.clone().lookup("log/app").ok().unwrap();
+    old.write_at(0, b"old").unwrap();
+
+    ramfs.rotate("/log/app", "/log/app.1").unwrap();
+    let rotated = root.clone().lookup("log/app.1").ok().unwrap();
+    assert!(Arc::ptr_eq(&rotated, &old));
+    let fresh = root.clone().lookup("log/app").ok().unwrap();
+    assert_eq!(fresh.get_attr().unwrap().size(), 0);
+    assert_eq!(ramfs.rotate("/log", "/log2"), Err(VfsError::IsADirectory));
+    assert_eq!(
+        ramfs.rotate("/log/app", "/log/app"),
+        Err(VfsError::InvalidInput)
+    );
+
+    let appender = {
+        let root = root.clone();
+        std::thread::spawn(move || {
+            for _ in 0..1000 {
+                let file = root.clone().lookup("log/app").ok().unwrap();
+                let size = file.get_attr().unwrap().size();
+                file.write_at(size, b"x").unwrap();
+            }
+        })
+    };
+    for _ in 0..100 {
+        ramfs.rotate("/log/app", "/log/app.1").unwrap();
+    }
+    appender.join().unwrap();
+}
+
+#[test]
+fn test_read_to_string() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let text = root
+        .get_or_init_file("text", || "héllo".as_bytes().to_vec())
+        .unwrap();
+    root.get_or_init_file("bin", || vec![b'a', 0xff, 0xfe])
+        .unwrap();
+
+    assert_eq!(text.read_to_string().unwrap(), "héllo");
+    assert_eq!(ramfs.read_to_string("/text").unwrap(), "héllo");
+    assert_eq!(ramfs.read_to_string("/bin"), Err(VfsError::InvalidData));
+    assert_eq!(ramfs.read_to_string("/"), Err(VfsError::IsADirectory));
+    assert_eq!(
+        ramfs.read_to_string("/none").err(),
+        Some(VfsError::NotFound)
+    );
+}
+
+#[test]
+fn test_seal_all() {
+    let ramfs = RamFileSystem::new();
+    ramfs
+        .root_dir_node()
+        .create_dir_all("etc")
+        .unwrap()
+        .create_file("hosts", b"localhost".to_vec())
+        .unwrap();
+
+    let sealed = ramfs.seal_all();
+    let reader = {
+        let sealed = sealed.clone();
+        std::thread::spawn(move || sealed.read_to_string("/etc/hosts").unwrap())
+    };
+    assert_eq!(reader.join().unwrap(), "localhost");
+    assert_eq!(sealed.stat("/etc/hosts").unwrap().size(), 9);
+    let mut buf = [0; 4];
+    assert_eq!(sealed.read_at("/etc/hosts", 5, &mut buf), Ok(4));
+    assert_eq!(&buf, b"host");
+    assert_eq!(sealed.read("/etc"), Err(VfsError::IsADirectory));
+
+    let hosts = sealed.lookup("/etc/hosts").ok().unwrap();
+    assert_eq!(hosts.write_at(0, b"x"), Err(VfsError::ReadOnlyFilesystem));
+    let etc = sealed.lookup("/etc").ok().unwrap();
+    assert_eq!(
+        etc.create("new", VfsNodeType::File),
+        Err(VfsError::ReadOnlyFilesystem)
+    );
+}
+
+#[test]
+fn test_seal_all_cannot_unseal() {
+    let ramfs = RamFileSystem::new();
+    ramfs.root_dir_node().create_file("f", Vec::new()).unwrap();
+    let sealed = ramfs.seal_all();
+
+    let root = downcast_node::<DirNode>(sealed.lookup("/").ok().unwrap()).unwrap();
+    root.set_subtree_readonly(false);
+    assert_eq!(
+        root.create_node("new", VfsNodeType::File),
+        Err(VfsError::ReadOnlyFilesystem)
+    );
+    let file = sealed.lookup("/f").ok().unwrap();
+    assert_eq!(file.write_at(0, b"x"), Err(VfsError::ReadOnlyFilesystem));
+    assert_eq!(root.remove_node("f"), Err(VfsError::ReadOnlyFilesystem));
+}
+
+#[test]
+fn test_seal_all_kept_handles() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let file = root.create_file_with("f", b"data").unwrap();
+    let sealed = ramfs.seal_all();
+
+    // handles obtained before sealing cannot mutate the tree either
+    let denied = Some(VfsError::ReadOnlyFilesystem);
+    let mut attr = file.get_attr().unwrap();
+    attr.set_uid(1000);
+    assert_eq!(
+        file.compare_and_set_attr(file.version(), attr).err(),
+        denied
+    );
+    assert_eq!(file.set_attr(&attr).err(), denied);
+    assert_eq!(file.chown(Some(1000), None).err(), denied);
+    assert_eq!(file.set_xattr("user.k", b"v").err(), denied);
+    assert_eq!(file.append(b"x").err(), denied);
+    assert_eq!(file.replace_contents(b"x").err(), denied);
+    let root_attr = root.get_attr().unwrap();
+    assert_eq!(
+        root.compare_and_set_attr(root.version(), root_attr).err(),
+        denied
+    );
+    assert_eq!(root.create_file_with("g", b"").err(), denied);
+    assert_eq!(root.create_dynamic_symlink("l", String::new).err(), denied);
+    assert_eq!(sealed.stat("/f").unwrap().uid(), 0);
+    assert_eq!(sealed.read_to_string("/f").unwrap(), "data");
+}
+
+#[test]
+fn test_birth_time() {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    let clock = Arc::new(AtomicU64::new(1_000_000_001));
+    let ramfs = RamFileSystem::new();
+    ramfs.set_time_source({
+        let clock = clock.clone();
+        move || clock.load(Ordering::Relaxed)
+    });
+    let root = ramfs.root_dir();
+    root.create("f", VfsNodeType::File).unwrap();
+    let f = root.clone().lookup("f").ok().unwrap();
+    let attr = f.get_attr().unwrap();
+    assert_eq!(attr.btime(), 1_000_000_001);
+    assert_eq!(attr.mtime(), 1_000_000_001);
+    assert_eq!(attr.ctime(), 1_000_000_001);
+
+    clock.store(2_000_000_002, Ordering::Relaxed);
+    f.write_at(0, b"data").unwrap();
+    let attr = f.get_attr().unwrap();
+    assert_eq!(attr.btime(), 1_000_000_001);
+    assert_eq!(attr.mtime(), 2_000_000_002);
+    assert_eq!(attr.ctime(), 2_000_000_002);
+
+    clock.store(3_000_000_003, Ordering::Relaxed);
+    let file = ramfs
+        .root_dir_node()
+        .get_or_init_file("f", Vec::new)
+        .unwrap();
+    file.compare_and_set_attr(file.version(), attr).unwrap();
+    let attr = f.get_attr().unwrap();
+    assert_eq!(attr.btime(), 1_000_000_001);
+    assert_eq!(attr.mtime(), 2_000_000_002);
+    assert_eq!(attr.ctime(), 3_000_000_003);
+}
+
+#[test]
+fn test_merge_from() {
+    let setup = || {
+        let ramfs = RamFileSystem::new();
+        let root = ramfs.root_dir_node();
+        let dst = root.create_dir_all("dst").unwrap();
+        let src = root.create_dir_all("src").unwrap();
+        dst.create_file("same", b"dst".to_vec()).unwrap();
+        src.create_file("same", b"src".to_vec()).unwrap();
+        src.create_dir_all("sub").unwrap();
+        (ramfs, dst, src)
+    };
+    let read = |ramfs: &RamFileSystem, path| ramfs.read_to_string(path).unwrap();
+
+    let (ramfs, dst, src) = setup();
+    assert_eq!(
+        dst.merge_from(&src, ConflictPolicy::Error),
+        Err(VfsError::AlreadyExists)
+    );
+    assert_eq!(src.get_entries().len(), 2);
+
+    dst.merge_from(&src, ConflictPolicy::Skip).unwrap();
+    assert!(src.get_entries().is_empty());
+    assert_eq!(read(&ramfs, "/dst/same"), "dst");
+    let sub = ramfs.root_dir().lookup("dst/sub").ok().unwrap();
+    let parent = sub.parent().unwrap();
+    assert!(Arc::ptr_eq(&parent, &(dst.clone() as VfsNodeRef)));
+    assert!(ramfs.root_dir().lookup("dst/sub/..").is_ok());
+
+    let (ramfs, dst, src) = setup();
+    dst.merge_from(&src, ConflictPolicy::Overwrite).unwrap();
+    assert!(src.get_entries().is_empty());
+    assert_eq!(read(&ramfs, "/dst/same"), "src");
+
+    let inner = dst.create_dir_all("sub/inner").unwrap();
+    assert_eq!(
+        inner.merge_from(&dst, ConflictPolicy::Skip),
+        Err(VfsError::InvalidInput)
+    );
+}
+
+/// Builds a ustar header block followed by the padded `data`.
+fn tar_entry(path: &str, typeflag: u8, link: &str, data: &[u8]) -> Vec<u8> {
+    let mut header = [0u8; 512];
+    header[..path.len()].copy_from_slice(path.as_bytes());
+    header[100..107].copy_from_slice(b"0000644");
+    header[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+    header[156] = typeflag;
+    header[157..157 + link.len()].copy_from_slice(link.as_bytes());
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[148..156].fill(b' ');
+    let sum: u32 = header.iter().map(|&b| b as u32).sum();
+    header[148..155].copy_from_slice(format!("{sum:06o}\0").as_bytes());
+
+    let mut entry = header.to_vec();
+    entry.extend_from_slice(data);
+    entry.resize(entry.len().div_ceil(512) * 512, 0);
+    entry
+}
+
+#[test]
+fn test_from_tar_limited() {
+    let mut tar = Vec::new();
+    tar.extend(tar_entry("etc/", b'5', "", b""));
+    tar.extend(tar_entry("etc/hosts", b'0', "", b"localhost"));
+    tar.extend(tar_entry("usr/share/big", b'0', "", &[7; 2000]));
+    tar.extend(tar_entry("dev/null", b'3', "", b""));
+    tar.extend([0; 1024]);
+
+    let ramfs = RamFileSystem::from_tar(&tar).unwrap();
+    assert_eq!(ramfs.read_to_string("/etc/hosts").unwrap(), "localhost");
+    let big = ramfs.root_dir().lookup("usr/share/big").ok().unwrap();
+    assert_eq!(big.get_attr().unwrap().size(), 2000);
+    assert!(ramfs.root_dir().lookup("dev/null").is_err());
+
+    let limits = TarLimits {
+        max_total_size: 1024,
+        ..Default::default()
+    };
+    assert_eq!(
+        RamFileSystem::from_tar_limited(&tar, limits).err(),
+        Some(VfsError::StorageFull)
+    );
+    let limits = TarLimits {
+        max_files: 1,
+        ..Default::default()
+    };
+    assert_eq!(
+        RamFileSystem::from_tar_limited(&tar, limits).err(),
+        Some(VfsError::StorageFull)
+    );
+    let limits = TarLimits {
+        max_depth: 2,
+        ..Default::default()
+    };
+    assert_eq!(
+        RamFileSystem::from_tar_limited(&tar, limits).err(),
+        Some(VfsError::NameTooLong)
+    );
+
+    // a header claiming a huge size is rejected without allocating it
+    let mut bomb = tar_entry("bomb", b'0', "", b"");
+    bomb[124..135].copy_from_slice(b"77777777777");
+    let sum: u32 = bomb[..512]
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| {
+            if (148..156).contains(&i) {
+                32
+            } else {
+                b as u32
+            }
+        })
+        .sum();
+    bomb[148..155].copy_from_slice(format!("{sum:06o}\0").as_bytes());
+    assert_eq!(
+        RamFileSystem::from_tar(&bomb).err(),
+        Some(VfsError::InvalidData)
+    );
+    tar[600] ^= 1;
+    assert_eq!(
+        RamFileSystem::from_tar(&tar).err(),
+        Some(VfsError::InvalidData)
+    );
+}
+
+#[test]
+fn test_detect_cycles() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let b = root.create_dir_all("a/b").unwrap();
+    root.create_dir_all("c").unwrap();
+    assert!(ramfs.detect_cycles().is_empty());
+
+    let up = root.clone().lookup("a").ok().unwrap();
+    assert_eq!(b.add_node("up", up.clone()), Err(VfsError::InvalidInput));
+    let shared = root.clone().lookup("c").ok().unwrap();
+    b.add_node("shared", shared.clone()).unwrap();
+    // `c` is still only a child of the root by its parent reference
+    shared.add_node("up", up).unwrap();
+    assert_eq!(ramfs.detect_cycles(), ["/a/b/shared/up"]);
+
+    ramfs.break_cycle("/a/b/shared/up").unwrap();
+    assert!(ramfs.detect_cycles().is_empty());
+    assert!(root.clone().lookup("a/b/shared").is_ok());
+}
+
+#[test]
+fn test_fetch_add_le_u64() {
+    let ramfs = RamFileSystem::new();
+    let counter = ramfs
+        .root_dir_node()
+        .get_or_init_file("counter", Vec::new)
+        .unwrap();
+    assert_eq!(counter.fetch_add_le_u64(5), Ok(0));
+    assert_eq!(counter.get_attr().unwrap().size(), 8);
+    assert_eq!(counter.fetch_add_le_u64(0), Ok(5));
+
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let counter = counter.clone();
+            std::thread::spawn(move || {
+                for _ in 0..1000 {
+                    counter.fetch_add_le_u64(1).unwrap();
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    let mut buf = [0; 8];
+    counter.read_at(0, &mut buf).unwrap();
+    assert_eq!(u64::from_le_bytes(buf), 4005);
+}
+
+#[test]
+fn test_entries_matching() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    for name in [".hidden", "a.txt", ".config", "b.rs", "c.txt"] {
+        root.create_node(name, VfsNodeType::File).unwrap();
+    }
+    assert_eq!(
+        root.entries_matching(|name| !name.starts_with('.')),
+        ["a.txt", "b.rs", "c.txt"]
+    );
+    assert_eq!(
+        root.entries_matching(|name| name.ends_with(".txt")),
+        ["a.txt", "c.txt"]
+    );
+}
+
+#[test]
+fn test_common_prefix() {
+    assert_eq!(
+        common_prefix(&["/a/b/c.txt", "/a/b/d/e", "/a/./b/../b/f"]),
+        "/a/b"
+    );
+    assert_eq!(common_prefix(&["/a/b", "/a/b/c"]), "/a/b");
+    assert_eq!(common_prefix(&["/a/bc", "/a/bd"]), "/a");
+    assert_eq!(common_prefix(&["/x/y", "/a/b"]), "/");
+    assert_eq!(common_prefix(&["x/y", "x/z"]), "x");
+    assert_eq!(common_prefix(&["x/y", "a/b"]), "");
+    assert_eq!(common_prefix(&["/x", "x"]), "");
+    assert_eq!(common_prefix(&[]), "");
+}
+
+#[test]
+fn test_into_subfs() {
+    let ramfs = RamFileSystem::new();
+    let sub = ramfs.root_dir_node().create_dir_all("sub").unwrap();
+    sub.create_file("f", b"shared".to_vec()).unwrap();
+    sub.create_dir_all("d").unwrap();
+
+    let subfs = sub.into_subfs().unwrap();
+    let sub_root = subfs.root_dir_node();
+    assert_eq!(sub_root.get_entries(), ["d", "f"]);
+    assert_eq!(subfs.read_to_string("/f").unwrap(), "shared");
+
+    sub_root.create_node("new", VfsNodeType::File).unwrap();
+    assert!(!sub.exist("new"));
+    sub.remove_node("f").unwrap();
+    assert!(sub_root.exist("f"));
+
+    // the content is shared until written
+    let f = sub_root.clone().lookup("f").ok().unwrap();
+    f.write_at(0, b"S").unwrap();
+    assert_eq!(subfs.read_to_string("/f").unwrap(), "Shared");
+    assert_eq!(subfs.path_of(&f).as_deref(), Some("/f"));
+    assert_eq!(subfs.stats().files, 2);
+    assert_eq!(subfs.stats().dirs, 2);
+}
+
+#[test]
+fn test_into_subfs_is_separate() {
+    let ramfs = RamFileSystem::new();
+    let sub = ramfs.root_dir_node().create_dir_all("sub").unwrap();
+    sub.create_file("f", b"data".to_vec()).unwrap();
+    sub.create_dir_all("d").unwrap();
+
+    let subfs = sub.into_subfs().unwrap();
+    let sub_root = subfs.root_dir_node();
+    let up = subfs.root_dir().lookup("d/..").ok().unwrap();
+    assert!(Arc::ptr_eq(&up, &(sub_root.clone() as VfsNodeRef)));
+
+    subfs.set_readonly(true);
+    let f = sub_root.clone().lookup("f").ok().unwrap();
+    assert_eq!(f.write_at(0, b"x"), Err(VfsError::ReadOnlyFilesystem));
+    assert_eq!(
+        subfs.root_dir().create("d/new", VfsNodeType::File),
+        Err(VfsError::ReadOnlyFilesystem)
+    );
+    // the original is still writable, and unaffected
+    ramfs
+        .root_dir()
+        .create("sub/d/new", VfsNodeType::File)
+        .unwrap();
+    assert!(sub_root.clone().lookup("d/new").is_err());
+}
+
+#[test]
+fn test_prune_empty_dirs() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_dir_all("top/empty/deeper/deepest").unwrap();
+    root.create_dir_all("top/empty/also").unwrap();
+    root.create_dir_all("top/kept/empty").unwrap();
+    root.create_dir_all("top/kept")
+        .unwrap()
+        .create_file("f", Vec::new())
+        .unwrap();
+
+    assert_eq!(ramfs.prune_empty_dirs("/top"), Ok(5));
+    let top = root.create_dir_all("top").unwrap();
+    assert_eq!(top.get_entries(), ["kept"]);
+    let kept = root.create_dir_all("top/kept").unwrap();
+    assert_eq!(kept.get_entries(), ["f"]);
+
+    root.create_dir_all("lonely").unwrap();
+    assert_eq!(ramfs.prune_empty_dirs("/lonely"), Ok(0));
+    assert!(root.exist("lonely"));
+    assert_eq!(
+        ramfs.prune_empty_dirs("/top/kept/f"),
+        Err(VfsError::NotADirectory)
+    );
+}
+
+#[test]
+fn test_sorted_iteration() {
+    let names = ["m", "b", "z", "a", "q"];
+    let build = |order: &[&str]| {
+        let ramfs = RamFileSystem::new();
+        for name in order {
+            let dir = ramfs.root_dir_node().create_dir_all(name).unwrap();
+            for name in order {
+                dir.create_file(name, name.as_bytes().to_vec()).unwrap();
+            }
+        }
+        ramfs
+    };
+    let mut reversed = names;
+    reversed.reverse();
+    let (fs1, fs2) = (build(&names), build(&reversed));
+
+    let mut dirents: Vec<_> = (0..8).map(|_| VfsDirEntry::default()).collect();
+    let n = fs1.root_dir().read_dir(0, &mut dirents).unwrap();
+    let listed: Vec<_> = dirents[2..n]
+        .iter()
+        .map(|ent| core::str::from_utf8(ent.name_as_bytes()).unwrap())
+        .collect();
+    assert_eq!(listed, ["a", "b", "m", "q", "z"]);
+
+    let walk = |fs: &RamFileSystem| {
+        let mut paths = Vec::new();
+        fs.root_dir_node()
+            .walk(&mut |path, _| paths.push(path.to_string()));
+        paths
+    };
+    let paths = walk(&fs1);
+    assert_eq!(paths, walk(&fs2));
+    assert!(paths.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(fs1.to_map(), fs2.to_map());
+}
+
+#[test]
+fn test_structural_eq() {
+    let fs1 = RamFileSystem::new();
+    fs1.root_dir_node()
+        .create_dir_all("a/b")
+        .unwrap()
+        .create_file("f", b"data".to_vec())
+        .unwrap();
+    fs1.root_dir_node().create_dir_all("c").unwrap();
+
+    let fs2 = RamFileSystem::new();
+    fs2.set_time_source(|| 42);
+    fs2.root_dir_node().create_dir_all("c").unwrap();
+    fs2.root_dir_node()
+        .create_dir_all("a/b")
+        .unwrap()
+        .create_file("f", b"data".to_vec())
+        .unwrap();
+    assert!(fs1.structural_eq(&fs2));
+
+    let f = fs2.root_dir().lookup("a/b/f").ok().unwrap();
+    f.write_at(3, b"A").unwrap();
+    assert!(!fs1.structural_eq(&fs2));
+    f.write_at(3, b"a").unwrap();
+    assert!(fs1.structural_eq(&fs2));
+    fs2.root_dir_node().create_dir_all("c/d").unwrap();
+    assert!(!fs1.structural_eq(&fs2));
+}
+
+#[test]
+fn test_structural_eq_symlinks() {
+    let fs1 = RamFileSystem::new();
+    let fs2 = RamFileSystem::new();
+    fs1.root_dir().symlink("a", "l").unwrap();
+    assert!(!fs1.structural_eq(&fs2));
+    fs2.root_dir().symlink("b", "l").unwrap();
+    assert!(!fs1.structural_eq(&fs2));
+    fs2.root_dir().remove("l").unwrap();
+    fs2.root_dir().symlink("a", "l").unwrap();
+    assert!(fs1.structural_eq(&fs2));
+}
+
+#[test]
+fn test_create_typed() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_typed("f", VfsNodeType::File).unwrap();
+    root.create_typed("d", VfsNodeType::Dir).unwrap();
+
+    assert_eq!(
+        root.create_typed("f", VfsNodeType::Dir),
+        Err(VfsError::NotADirectory)
+    );
+    assert_eq!(
+        root.create_typed("d", VfsNodeType::File),
+        Err(VfsError::IsADirectory)
+    );
+    assert_eq!(
+        root.create_typed("f", VfsNodeType::File),
+        Err(VfsError::AlreadyExists)
+    );
+    assert_eq!(
+        root.create_typed("d", VfsNodeType::Dir),
+        Err(VfsError::AlreadyExists)
+    );
+    assert_eq!(
+        root.create_node("d", VfsNodeType::File),
+        Err(VfsError::AlreadyExists)
+    );
+}
+
+#[test]
+fn test_create_files() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_files(&["a", "b", "c"]).unwrap();
+    assert_eq!(root.get_entries(), ["a", "b", "c"]);
+    assert_eq!(root.create_files(&["d", "a"]), Err(VfsError::AlreadyExists));
+    assert_eq!(root.create_files(&["e", "e"]), Err(VfsError::AlreadyExists));
+    assert_eq!(root.get_entries(), ["a", "b", "c"]);
+
+    // compare bulk creation with one call per file
+    let names: Vec<_> = (0..10_000).map(|i| format!("file{i}")).collect();
+    let names: Vec<_> = names.iter().map(String::as_str).collect();
+    let bulk = root.create_dir_all("bulk").unwrap();
+    let single = root.create_dir_all("single").unwrap();
+
+    let start = std::time::Instant::now();
+    bulk.create_files(&names).unwrap();
+    let bulk_time = start.elapsed();
+    let start = std::time::Instant::now();
+    for name in &names {
+        single.create_node(name, VfsNodeType::File).unwrap();
+    }
+    let single_time = start.elapsed();
+
+    assert_eq!(bulk.get_entries(), single.get_entries());
+    println!(
+        "create 10k files: bulk {bulk_time:?}, individual {single_time:?} ({:.2}x)",
+        single_time.as_secs_f64() / bulk_time.as_secs_f64()
+    );
+}
+
+#[test]
+fn test_lookup_large_dir() {
+    // long common prefixes are the slow case for the sorted map alone
+    let names: Vec<_> = (0..10_000)
+        .map(|i| format!("{}{i}", "session_".repeat(8)))
+        .collect();
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_files(&names.iter().map(String::as_str).collect::<Vec<_>>())
+        .unwrap();
+
+    let start = std::time::Instant::now();
+    for name in &names {
+        assert!(root.clone().lookup(name).is_ok());
+    }
+    let time = start.elapsed();
+    println!(
+        "look up 10k entries: {time:?} ({:?} each)",
+        time / names.len() as u32
+    );
+}
+
+#[test]
+fn test_first_last_entry() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    assert!(root.first_entry().is_none());
+    assert!(root.last_entry().is_none());
+
+    for name in ["m", "Z", "b", "a0", "é"] {
+        root.create_node(name, VfsNodeType::File).unwrap();
+    }
+    let (first, node) = root.first_entry().unwrap();
+    assert_eq!(first, "Z"); // uppercase sorts before lowercase
+    assert!(Arc::ptr_eq(&node, &root.clone().lookup("Z").ok().unwrap()));
+    assert_eq!(root.last_entry().unwrap().0, "é"); // non-ASCII sorts last
+}
+
+#[test]
+fn test_dir_rename() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let a = root.create_dir_all("a").unwrap();
+    let b = root.create_dir_all("b").unwrap();
+    a.create_file("f", b"content".to_vec()).unwrap();
+    let sub = a.create_dir_all("sub").unwrap();
+
+    a.rename("f", &b, "g").unwrap();
+    assert!(!a.exist("f"));
+    assert_eq!(ramfs.read_to_string("/b/g").unwrap(), "content");
+    b.rename("g", &b, "h").unwrap();
+    assert_eq!(b.get_entries(), ["h"]);
+
+    a.rename("sub", &b, "sub").unwrap();
+    let parent = sub.parent().unwrap();
+    assert!(Arc::ptr_eq(&parent, &(b.clone() as VfsNodeRef)));
+
+    assert_eq!(a.rename("none", &b, "x"), Err(VfsError::NotFound));
+    b.create_file("h2", Vec::new()).unwrap();
+    assert_eq!(b.rename("h", &b, "h2"), Err(VfsError::AlreadyExists));
+    assert_eq!(root.rename("b", &sub, "b"), Err(VfsError::InvalidInput));
+}
+
+struct TestDevice;
+
+impl VfsNodeOps for TestDevice {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::default_file(),
+            VfsNodeType::CharDevice,
+            0,
+            0,
+        ))
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+#[test]
+fn test_mount_options() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    ramfs.set_nodev(true);
+    assert_eq!(
+        root.add_node("tty", Arc::new(TestDevice)),
+        Err(VfsError::PermissionDenied)
+    );
+    assert_eq!(
+        root.create("blk", VfsNodeType::BlockDevice),
+        Err(VfsError::PermissionDenied)
+    );
+    root.create("file", VfsNodeType::File).unwrap();
+    ramfs.set_nodev(false);
+    root.add_node("tty", Arc::new(TestDevice)).unwrap();
+
+    ramfs.set_noexec(true);
+    let file = ramfs
+        .root_dir_node()
+        .get_or_init_file("file", Vec::new)
+        .unwrap();
+    let mut attr = file.get_attr().unwrap();
+    attr.set_perm(VfsNodePerm::from_bits_truncate(0o755));
+    file.compare_and_set_attr(file.version(), attr).unwrap();
+    assert_eq!(file.get_attr().unwrap().perm().bits(), 0o644);
+}
+
+#[test]
+fn test_remove_all() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let b = root.create_dir_all("tmp/a/b").unwrap();
+    b.create_file("f1", vec![0; 100]).unwrap();
+    root.create_dir_all("tmp/a")
+        .unwrap()
+        .create_file("f2", vec![0; 100])
+        .unwrap();
+    root.create_dir_all("tmp/c").unwrap();
+    root.create_file("single", vec![0; 10]).unwrap();
+    assert_eq!(ramfs.memory_usage(), 210);
+
+    b.set_subtree_readonly(true);
+    assert_eq!(root.remove_all("tmp"), Err(VfsError::ReadOnlyFilesystem));
+    assert!(root.exist("tmp"));
+    b.set_subtree_readonly(false);
+    drop(b);
+
+    // tmp, a, b, f1, f2, c
+    assert_eq!(root.remove_all("tmp"), Ok(6));
+    assert!(!root.exist("tmp"));
+    assert_eq!(ramfs.memory_usage(), 10);
+    assert_eq!(root.remove_all("single"), Ok(1));
+    assert_eq!(ramfs.memory_usage(), 0);
+    assert_eq!(root.remove_all("single"), Err(VfsError::NotFound));
+}
+
+#[test]
+fn test_link() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let dir = root.create_dir_all("d").unwrap();
+    root.create_file("f", b"shared".to_vec()).unwrap();
+    let f = root.clone().lookup("f").ok().unwrap();
+    let nlink = |node: &VfsNodeRef| node.get_attr().unwrap().nlink();
+    assert_eq!(nlink(&f), 1);
+
+    dir.link_node("g", &f).unwrap();
+    root.link_node("h", &f).unwrap();
+    assert_eq!(nlink(&f), 3);
+    assert_eq!(root.link_node("h", &f), Err(VfsError::AlreadyExists));
+    let d = root.clone().lookup("d").ok().unwrap();
+    assert_eq!(root.link_node("d2", &d), Err(VfsError::PermissionDenied));
+    root.create("p", VfsNodeType::Fifo).unwrap();
+    assert_eq!(ramfs.link("p", "q"), Err(VfsError::PermissionDenied));
+    assert!(!root.exist("q"));
+    assert_eq!(nlink(&root.clone().lookup("p").ok().unwrap()), 1);
+
+    let g = root.clone().lookup("d/g").ok().unwrap();
+    assert!(Arc::ptr_eq(&f, &g));
+    g.write_at(0, b"S").unwrap();
+    assert_eq!(ramfs.read_to_string("/h").unwrap(), "Shared");
+
+    drop((f, g));
+    root.remove_node("f").unwrap();
+    ramfs.rename("/h", "/d/g").unwrap(); // a no-op, like rename(2)
+    let g = root.clone().lookup("d/g").ok().unwrap();
+    assert_eq!(nlink(&g), 2);
+    ramfs.rename("/h", "/d/h").unwrap();
+    dir.remove_node("h").unwrap();
+    assert_eq!(nlink(&g), 1);
+    assert_eq!(ramfs.read_to_string("/d/g").unwrap(), "Shared");
+    assert_eq!(ramfs.memory_usage(), 6);
+    drop(g);
+    dir.remove_node("g").unwrap();
+    assert_eq!(ramfs.memory_usage(), 0);
+}
+
+#[test]
+fn test_truncate_zero_fill() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("f", VfsNodeType::File).unwrap();
+    let f = root.lookup("f").ok().unwrap();
+    let mut buf = vec![0xaa; 5000];
+
+    f.truncate(4096).unwrap();
+    assert_eq!(f.get_attr().unwrap().size(), 4096);
+    assert_eq!(f.read_at(0, &mut buf), Ok(4096));
+    assert!(buf[..4096].iter().all(|&b| b == 0));
+
+    f.write_at(0, b"abc").unwrap();
+    f.truncate(4096).unwrap();
+    assert_eq!(f.get_attr().unwrap().size(), 4096);
+    assert_eq!(f.read_at(0, &mut buf[..3]), Ok(3));
+    assert_eq!(&buf[..3], b"abc");
+
+    f.truncate(0).unwrap();
+    assert_eq!(f.get_attr().unwrap().size(), 0);
+    assert_eq!(f.read_at(0, &mut buf), Ok(0));
+    assert_eq!(f.read_at(u64::MAX, &mut buf), Ok(0));
+    assert_eq!(ramfs.memory_usage(), 0);
+
+    f.write_at(0, b"xyz").unwrap();
+    f.truncate(1).unwrap();
+    f.truncate(3).unwrap();
+    assert_eq!(f.read_at(0, &mut buf), Ok(3));
+    assert_eq!(&buf[..3], b"x\0\0");
+}
+
+#[test]
+fn test_append() {
+    let ramfs = RamFileSystem::new();
+    let log = ramfs
+        .root_dir_node()
+        .get_or_init_file("log", Vec::new)
+        .unwrap();
+    assert_eq!(log.append(b"head\n"), Ok((5, 5)));
+
+    let threads: Vec<_> = b"ab"
+        .iter()
+        .map(|&tag| {
+            let log = log.clone();
+            std::thread::spawn(move || {
+                for _ in 0..1000 {
+                    log.append(&[tag; 8]).unwrap();
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert_eq!(log.get_attr().unwrap().size(), 5 + 2 * 8 * 1000);
+    log.with_range(5, usize::MAX, |data| {
+        // records are never interleaved
+        assert!(data.chunks(8).all(|rec| rec.iter().all(|&b| b == rec[0])));
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_timestamps() {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    let clock = Arc::new(AtomicU64::new(0));
+    let ramfs = RamFileSystem::new();
+    ramfs.set_time_source({
+        let clock = clock.clone();
+        move || clock.load(Ordering::Relaxed)
+    });
+    let times = |node: &VfsNodeRef| {
+        let attr = node.get_attr().unwrap();
+        (attr.atime(), attr.mtime(), attr.ctime())
+    };
+    let root = ramfs.root_dir();
+    clock.store(20, Ordering::Relaxed);
+    root.create("d", VfsNodeType::Dir).unwrap();
+    let d = root.clone().lookup("d").ok().unwrap();
+    assert_eq!(times(&d), (20, 20, 20));
+    assert_eq!(times(&root), (0, 20, 20));
+
+    clock.store(30, Ordering::Relaxed);
+    d.create("f", VfsNodeType::File).unwrap();
+    let f = d.clone().lookup("f").ok().unwrap();
+    assert_eq!(times(&d), (20, 30, 30));
+    assert_eq!(times(&f), (30, 30, 30));
+
+    clock.store(40, Ordering::Relaxed);
+    f.write_at(0, b"data").unwrap();
+    assert_eq!(times(&f), (30, 40, 40));
+    clock.store(50, Ordering::Relaxed);
+    f.read_at(0, &mut [0; 4]).unwrap();
+    assert_eq!(times(&f), (50, 40, 40));
+
+    clock.store(60, Ordering::Relaxed);
+    let mut dirents: Vec<_> = (0..4).map(|_| VfsDirEntry::default()).collect();
+    d.read_dir(0, &mut dirents).unwrap();
+    assert_eq!(times(&d), (60, 30, 30));
+    d.remove("f").unwrap();
+    assert_eq!(times(&d), (60, 60, 60));
+}
+
+#[test]
+fn test_set_attr() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("f", VfsNodeType::File).unwrap();
+    root.create("d", VfsNodeType::Dir).unwrap();
+    let perm = VfsNodePerm::from_bits_truncate(0o755);
+    for name in ["f", "d"] {
+        let node = root.clone().lookup(name).ok().unwrap();
+        let mut attr = node.get_attr().unwrap();
+        attr.set_perm(perm);
+        node.set_attr(&attr).unwrap();
+        assert_eq!(node.get_attr().unwrap().perm().bits(), 0o755);
+    }
+    let dir = root.clone().lookup("d").ok().unwrap();
+    dir.set_attr(&VfsNodeAttr::new_file(0, 0)).unwrap();
+    let attr = dir.get_attr().unwrap();
+    assert!(attr.is_dir());
+    assert_eq!(attr.perm().bits(), VfsNodePerm::default_file().bits());
+}
+
+#[test]
+fn test_capacity() {
+    let ramfs = RamFileSystem::with_capacity(10);
+    assert_eq!(ramfs.capacity(), 10);
+    let root = ramfs.root_dir();
+    root.create("f", VfsNodeType::File).unwrap();
+    root.create("d", VfsNodeType::Dir).unwrap();
+    let f = root.clone().lookup("f").ok().unwrap();
+    assert_eq!(f.write_at(0, b"12345678").unwrap(), 8);
+    assert_eq!(ramfs.memory_usage(), 8);
+
+    assert_eq!(f.write_at(6, b"abcdef").err(), Some(VfsError::StorageFull));
+    assert_eq!(f.truncate(11).err(), Some(VfsError::StorageFull));
+    let mut buf = [0; 16];
+    assert_eq!(f.read_at(0, &mut buf).unwrap(), 8);
+    assert_eq!(&buf[..8], b"12345678");
+    assert_eq!(ramfs.memory_usage(), 8);
+
+    f.truncate(10).unwrap();
+    assert_eq!(ramfs.memory_usage(), 10);
+    drop(f);
+    root.remove("f").unwrap();
+    assert_eq!(ramfs.memory_usage(), 0);
+    root.create("g", VfsNodeType::File).unwrap();
+    let g = root.clone().lookup("g").ok().unwrap();
+    assert_eq!(g.write_at(0, b"0123456789").unwrap(), 10);
+}
+
+#[test]
+fn test_stats() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    assert_eq!(
+        ramfs.stats(),
+        RamFsStats {
+            total_bytes: 0,
+            files: 0,
+            dirs: 1,
+            symlinks: 0,
+        }
+    );
+    root.create("d", VfsNodeType::Dir).unwrap();
+    root.create("d/f", VfsNodeType::File).unwrap();
+    root.create("g", VfsNodeType::File).unwrap();
+    let f = root.clone().lookup("d/f").ok().unwrap();
+    f.write_at(0, b"hello").unwrap();
+    root.link_node("h", &f).unwrap();
+    let stats = ramfs.stats();
+    assert_eq!((stats.total_bytes, stats.files, stats.dirs), (5, 2, 2));
+
+    drop(f);
+    root.remove("h").unwrap();
+    assert_eq!(ramfs.stats().files, 2);
+    root.remove_all("d").unwrap();
+    root.remove("g").unwrap();
+    assert_eq!(
+        ramfs.stats(),
+        RamFsStats {
+            total_bytes: 0,
+            files: 0,
+            dirs: 1,
+            symlinks: 0,
+        }
+    );
+}
+
+#[test]
+fn test_lookup_follow() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("d", VfsNodeType::Dir).unwrap();
+    root.create("d/f", VfsNodeType::File).unwrap();
+
+    root.symlink("/b", "a").unwrap();
+    root.symlink("/a", "b").unwrap();
+    assert_eq!(
+        root.clone().lookup_follow("a", 40).err(),
+        Some(VfsError::FilesystemLoop)
+    );
+    let mut buf = [0; 8];
+    assert_eq!(root.readlink("a", &mut buf).unwrap(), 2);
+    assert_eq!(&buf[..2], b"/b");
+    assert!(root.clone().lookup("a").ok().unwrap().is_symlink());
+
+    // l1 -> d/l2 -> l3 -> ... -> d/l8 -> f, with targets relative to the
+    // directory containing each link
+    for i in 1..=8 {
+        let (dir, target) = match (i % 2 == 1, i == 8) {
+            (_, true) => ("d", "f".into()),
+            (true, false) => ("", format!("d/l{}", i + 1)),
+            (false, false) => ("d", format!("../l{}", i + 1)),
+        };
+        let path = if dir.is_empty() {
+            format!("l{i}")
+        } else {
+            format!("{dir}/l{i}")
+        };
+        root.symlink(&target, &path).unwrap();
+    }
+    let file = root.clone().lookup("d/f").ok().unwrap();
+    let node = root.clone().lookup_follow("l1", 8).ok().unwrap();
+    assert!(Arc::ptr_eq(&node, &file));
+    assert_eq!(
+        root.clone().lookup_follow("l1", 7).err(),
+        Some(VfsError::FilesystemLoop)
+    );
+    assert_eq!(ramfs.stats().symlinks, 10);
+}
+
+#[test]
+fn test_readlink_short_buffer() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.symlink("some/long/target", "l").unwrap();
+    let link = root.clone().lookup("l").ok().unwrap();
+    let link = link.as_any().downcast_ref::<SymlinkNode>().unwrap();
+    assert_eq!(link.target_len().unwrap(), 16);
+
+    let mut buf = [0; 4];
+    assert_eq!(
+        root.readlink("l", &mut buf).err(),
+        Some(VfsError::OutOfRange)
+    );
+    assert_eq!(&buf, b"some");
+    let mut buf = [0; 16];
+    assert_eq!(link.readlink("", &mut buf).unwrap(), 16);
+    assert_eq!(&buf, b"some/long/target");
+}
+
+#[test]
+fn test_case_insensitive() {
+    let ramfs = RamFileSystem::new_case_insensitive();
+    let root = ramfs.root_dir_node();
+    root.create("Docs", VfsNodeType::Dir).unwrap();
+    root.create("docs/ReadMe.TXT", VfsNodeType::File).unwrap();
+    let file = root.clone().lookup("DOCS/readme.txt").ok().unwrap();
+    assert!(Arc::ptr_eq(
+        &file,
+        &root.clone().lookup("Docs/ReadMe.TXT").ok().unwrap()
+    ));
+    assert_eq!(
+        root.create("docs/README.txt", VfsNodeType::File).err(),
+        Some(VfsError::AlreadyExists)
+    );
+    assert_eq!(root.get_entries(), ["Docs"]);
+    let docs = root.clone().lookup("docs").ok().unwrap();
+    let mut dirents: Vec<_> = (0..4).map(|_| VfsDirEntry::default()).collect();
+    assert_eq!(docs.read_dir(0, &mut dirents).unwrap(), 3);
+    assert_eq!(dirents[2].name_as_bytes(), b"ReadMe.TXT");
+
+    VfsNodeOps::rename(&*root, "docs/readme.txt", "docs/README.md").unwrap();
+    assert!(Arc::ptr_eq(
+        &file,
+        &root.clone().lookup("docs/readme.MD").ok().unwrap()
+    ));
+    VfsNodeOps::rename(&*root, "DOCS", "docs").unwrap();
+    assert_eq!(root.get_entries(), ["docs"]);
+    root.remove("docs/readme.md").unwrap();
+    root.remove("DOCS").unwrap();
+    assert!(root.get_entries().is_empty());
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("a", VfsNodeType::File).unwrap();
+    root.create("A", VfsNodeType::File).unwrap();
+    assert_eq!(root.get_entries(), ["A", "a"]);
+}
+
+#[test]
+fn test_snapshot() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("d", VfsNodeType::Dir).unwrap();
+    root.create("d/f", VfsNodeType::File).unwrap();
+    let f = root.clone().lookup("d/f").ok().unwrap();
+    f.write_at(0, b"before").unwrap();
+    root.link_node("g", &f).unwrap();
+    root.symlink("d/f", "l").unwrap();
+    root.add_node("dev", Arc::new(TestDevice)).unwrap();
+
+    let snapshot = ramfs.snapshot().unwrap();
+    assert!(snapshot.structural_eq(&ramfs));
+    f.write_at(0, b"after!").unwrap();
+    root.remove("g").unwrap();
+    root.create("new", VfsNodeType::File).unwrap();
+
+    let snap_root = snapshot.root_dir_node();
+    assert_eq!(snap_root.get_entries(), ["d", "dev", "g", "l"]);
+    let copy = snap_root.clone().lookup("d/f").ok().unwrap();
+    assert!(Arc::ptr_eq(
+        &copy,
+        &snap_root.clone().lookup("g").ok().unwrap()
+    ));
+    assert_eq!(copy.get_attr().unwrap().nlink(), 2);
+    assert_eq!(snapshot.read_to_string("/d/f").unwrap(), "before");
+    let target = snap_root.clone().lookup_follow("l", 1).ok().unwrap();
+    assert!(Arc::ptr_eq(&target, &copy));
+    let d = snap_root.clone().lookup("d").ok().unwrap();
+    assert!(Arc::ptr_eq(
+        &d.parent().unwrap(),
+        &(snap_root as VfsNodeRef)
+    ));
+}
+
+#[test]
+fn test_snapshot_readonly() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_all("ro/f", VfsNodeType::File).unwrap();
+    root.create_all("rw/f", VfsNodeType::File).unwrap();
+    let f = root.clone().lookup("ro/f").ok().unwrap();
+    let mut attr = f.get_attr().unwrap();
+    attr.set_perm(VfsNodePerm::from_bits_truncate(0o600));
+    f.set_attr(&attr).unwrap();
+    let ro = downcast_node::<DirNode>(root.clone().lookup("ro").ok().unwrap()).unwrap();
+    ro.set_subtree_readonly(true);
+    ramfs.set_readonly(true);
+
+    let snapshot = ramfs.snapshot().unwrap();
+    assert!(snapshot.is_readonly());
+    assert!(snapshot.structural_eq(&ramfs));
+    let copy = snapshot.root_dir_node().lookup("ro/f").ok().unwrap();
+    assert_eq!(copy.get_attr().unwrap().perm().bits(), 0o600);
+
+    snapshot.set_readonly(false);
+    let snap_root = snapshot.root_dir_node();
+    assert_eq!(
+        snap_root.create("ro/g", VfsNodeType::File),
+        Err(VfsError::ReadOnlyFilesystem)
+    );
+    snap_root.create("rw/g", VfsNodeType::File).unwrap();
+}
+
+#[test]
+fn test_export_import() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("etc", VfsNodeType::Dir).unwrap();
+    root.create("etc/empty", VfsNodeType::Dir).unwrap();
+    root.create("etc/hosts", VfsNodeType::File).unwrap();
+    root.create("bin", VfsNodeType::File).unwrap();
+    root.symlink("etc/hosts", "hosts").unwrap();
+    root.add_node("dev", Arc::new(TestDevice)).unwrap();
+    let hosts = root.clone().lookup("etc/hosts").ok().unwrap();
+    hosts.write_at(0, b"127.0.0.1 localhost\n").unwrap();
+    let bin = root.clone().lookup("bin").ok().unwrap();
+    let mut attr = bin.get_attr().unwrap();
+    attr.set_perm(VfsNodePerm::from_bits_truncate(0o755));
+    bin.set_attr(&attr).unwrap();
+
+    let image = ramfs.export().unwrap();
+    let restored = RamFileSystem::import(&image).unwrap();
+    assert!(restored.structural_eq(&ramfs));
+    let restored_root = restored.root_dir_node();
+    assert_eq!(restored_root.get_entries(), ["bin", "etc", "hosts"]);
+    let bin = restored_root.clone().lookup("bin").ok().unwrap();
+    assert_eq!(bin.get_attr().unwrap().perm().bits(), 0o755);
+    let link = restored_root
+        .clone()
+        .lookup_follow("hosts", 1)
+        .ok()
+        .unwrap();
+    assert_eq!(link.get_attr().unwrap().size(), 20);
+    assert_eq!(restored.export(), Ok(image.clone()));
+
+    assert_eq!(
+        RamFileSystem::import(&image[..image.len() - 1]).err(),
+        Some(VfsError::InvalidData)
+    );
+    let mut bad = image.clone();
+    bad[4] = 2;
+    assert_eq!(
+        RamFileSystem::import(&bad).err(),
+        Some(VfsError::InvalidData)
+    );
+
+    // lengths that do not fit in the image are rejected, not truncated
+    ramfs.set_max_symlink_len(1 << 17);
+    root.symlink(&"t".repeat(1 << 16), "long").unwrap();
+    assert_eq!(ramfs.export(), Err(VfsError::NameTooLong));
+    root.remove("long").unwrap();
+    root.create(&"n".repeat(1 << 16), VfsNodeType::File)
+        .unwrap();
+    assert_eq!(ramfs.export(), Err(VfsError::NameTooLong));
+    assert_eq!(root.encode_listing(), Err(VfsError::NameTooLong));
+}
+
+#[test]
+fn test_load_tar() {
+    let mut tar = Vec::new();
+    tar.extend(tar_entry("bin/sh", b'0', "", b"#!"));
+    tar.extend(tar_entry("bin/", b'5', "", b""));
+    tar.extend(tar_entry("etc/init.d/rcS", b'0', "", b"mount -a\n"));
+    tar.extend(tar_entry("etc/motd", b'0', "", b"hi"));
+    tar.extend(tar_entry("bin/ash", b'2', "sh", b""));
+    tar.extend(tar_entry("dev/console", b'3', "", b""));
+    tar.extend([0; 1024]);
+
+    let ramfs = RamFileSystem::new();
+    ramfs.root_dir().create("etc", VfsNodeType::Dir).unwrap();
+    ramfs.load_tar(&tar).unwrap();
+    assert_eq!(
+        ramfs.read_to_string("/etc/init.d/rcS").unwrap(),
+        "mount -a\n"
+    );
+    assert_eq!(ramfs.read_to_string("/etc/motd").unwrap(), "hi");
+    let root = ramfs.root_dir_node();
+    assert_eq!(root.get_entries(), ["bin", "etc"]);
+    let sh = root.clone().lookup("bin/sh").ok().unwrap();
+    let ash = root.clone().lookup_follow("bin/ash", 1).ok().unwrap();
+    assert!(Arc::ptr_eq(&sh, &ash));
+    assert!(root.clone().lookup("dev/console").is_err());
+}
+
+#[test]
+fn test_load_tar_replaces_symlink() {
+    let mut tar = Vec::new();
+    tar.extend(tar_entry("etc/motd", b'0', "", b"hi"));
+    tar.extend(tar_entry("etc/issue", b'2', "motd", b""));
+    tar.extend(tar_entry("etc/issue", b'0', "", b"welcome"));
+    tar.extend([0; 1024]);
+
+    let ramfs = RamFileSystem::new();
+    ramfs.load_tar(&tar).unwrap();
+    let issue = ramfs.root_dir_node().lookup("etc/issue").ok().unwrap();
+    assert_eq!(issue.get_attr().unwrap().file_type(), VfsNodeType::File);
+    assert_eq!(ramfs.read_to_string("/etc/issue").unwrap(), "welcome");
+    assert_eq!(ramfs.read_to_string("/etc/motd").unwrap(), "hi");
+}
+
+#[test]
+fn test_load_tar_keeps_directories() {
+    for kind in *b"02" {
+        let mut tar = Vec::new();
+        tar.extend(tar_entry("etc/", b'5', "", b""));
+        tar.extend(tar_entry("etc", kind, "motd", b""));
+        tar.extend([0; 1024]);
+
+        let ramfs = RamFileSystem::new();
+        assert_eq!(ramfs.load_tar(&tar), Err(VfsError::InvalidData));
+        let etc = ramfs.root_dir_node().lookup("etc").ok().unwrap();
+        assert!(etc.get_attr().unwrap().is_dir());
+    }
+}
+
+#[test]
+fn test_dynamic_symlink_cached() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let generator = |calls: Arc<AtomicUsize>| {
+        move || format!("/proc/{}", calls.fetch_add(1, Ordering::Relaxed))
+    };
+    root.create_dynamic_symlink("self", generator(calls.clone()))
+        .unwrap();
+    root.create_dynamic_symlink_cached("cached", generator(calls.clone()))
+        .unwrap();
+
+    let node = root.clone().lookup("self").ok().unwrap();
+    let mut buf = [0; 16];
+    assert_eq!(node.get_attr().unwrap().size(), 7);
+    assert_eq!(node.readlink("", &mut buf).unwrap(), 7);
+    assert_eq!(&buf[..7], b"/proc/1");
+    assert_eq!(calls.load(Ordering::Relaxed), 2);
+
+    calls.store(0, Ordering::Relaxed);
+    let node = root.clone().lookup("cached").ok().unwrap();
+    assert_eq!(node.get_attr().unwrap().size(), 7);
+    assert_eq!(node.readlink("", &mut buf).unwrap(), 7);
+    assert_eq!(&buf[..7], b"/proc/0");
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+    let link = node.as_any().downcast_ref::<SymlinkNode>().unwrap();
+    assert!(link.is_dynamic());
+    link.invalidate();
+    assert_eq!(link.target().unwrap(), "/proc/1");
+    assert_eq!(link.target().unwrap(), "/proc/1");
+    assert_eq!(calls.load(Ordering::Relaxed), 2);
+
+    let image = ramfs.export().unwrap();
+    assert!(RamFileSystem::import(&image)
+        .unwrap()
+        .root_dir_node()
+        .get_entries()
+        .is_empty());
+}
+
+#[test]
+fn test_clear() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    for i in 0..50 {
+        let ty = if i % 5 == 0 {
+            VfsNodeType::Dir
+        } else {
+            VfsNodeType::File
+        };
+        root.create(&format!("e{i}"), ty).unwrap();
+    }
+    root.create("e0/inner", VfsNodeType::File).unwrap();
+    let kept = root.clone().lookup("e1").ok().unwrap();
+    kept.write_at(0, b"still here").unwrap();
+    let dir = root.clone().lookup("e0").ok().unwrap();
+
+    ramfs.clear().unwrap();
+    assert!(root.get_entries().is_empty());
+    assert!(Arc::ptr_eq(&ramfs.root_dir_node(), &root));
+    assert_eq!(ramfs.stats().files, 2);
+    assert_eq!(ramfs.peak_memory_usage(), 10);
+    let mut buf = [0; 10];
+    assert_eq!(kept.read_at(0, &mut buf).unwrap(), 10);
+    assert!(dir.clone().lookup("inner").is_ok());
+
+    drop((kept, dir));
+    assert_eq!(ramfs.stats().files, 0);
+    root.create("again", VfsNodeType::File).unwrap();
+    assert_eq!(root.get_entries(), ["again"]);
+}
+
+#[test]
+fn test_dir_len() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    assert!(root.is_empty());
+    assert_eq!(root.len(), 0);
+    root.create("d", VfsNodeType::Dir).unwrap();
+    root.create("f", VfsNodeType::File).unwrap();
+    root.create("d/g", VfsNodeType::File).unwrap();
+    assert!(!root.is_empty());
+    assert_eq!(root.len(), 2);
+    assert_eq!(root.remove("d").err(), Some(VfsError::DirectoryNotEmpty));
+    root.remove("d/g").unwrap();
+    root.remove("d").unwrap();
+    assert_eq!(root.len(), 1);
+}
+
+#[test]
+fn test_walk() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    for dir in ["a", "a/b", "a/b/c", "z"] {
+        root.create(dir, VfsNodeType::Dir).unwrap();
+    }
+    for file in ["a/b/c/f", "a/g", "h"] {
+        root.create(file, VfsNodeType::File).unwrap();
+    }
+    root.symlink("/", "a/b/up").unwrap();
+
+    let mut paths = Vec::new();
+    root.walk(&mut |path, node| {
+        // the lock of the parent is not held
+        assert!(root.clone().lookup(path).is_ok());
+        paths.push((path.to_string(), node.is_symlink()));
+    });
+    let expected = ["a", "a/b", "a/b/c", "a/b/c/f", "a/b/up", "a/g", "h", "z"];
+    assert_eq!(
+        paths.iter().map(|(p, _)| p.as_str()).collect::<Vec<_>>(),
+        expected
+    );
+    assert_eq!(paths.iter().filter(|(_, link)| *link).count(), 1);
+}
+
+/// A node whose `get_attr` fails once `broken` is set.
+struct FlakyNode {
+    broken: std::sync::atomic::AtomicBool,
+}
+
+impl VfsNodeOps for FlakyNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        match self.broken.load(std::sync::atomic::Ordering::Relaxed) {
+            true => Err(VfsError::Io),
+            false => Ok(VfsNodeAttr::new_file(0, 0)),
+        }
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+#[test]
+fn test_read_dir_attr_error() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    let flaky = Arc::new(FlakyNode {
+        broken: false.into(),
+    });
+    root.create("a", VfsNodeType::File).unwrap();
+    root.add_node("b", flaky.clone()).unwrap();
+    let mut dirents: Vec<_> = (0..4).map(|_| VfsDirEntry::default()).collect();
+    assert_eq!(root.read_dir(0, &mut dirents).unwrap(), 4);
+
+    flaky
+        .broken
+        .store(true, std::sync::atomic::Ordering::Relaxed);
+    assert_eq!(root.read_dir(0, &mut dirents).err(), Some(VfsError::Io));
+    assert_eq!(root.read_dir(2, &mut dirents[..1]).unwrap(), 1);
+}
+
+#[test]
+fn test_create_all() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_all("x/y/z", VfsNodeType::File).unwrap();
+    for (path, is_dir) in [("x", true), ("x/y", true), ("x/y/z", false)] {
+        let node = root.clone().lookup(path).ok().unwrap();
+        assert_eq!(node.get_attr().unwrap().is_dir(), is_dir);
+    }
+    root.create_all("x/y/w/", VfsNodeType::Dir).unwrap();
+    root.create_all("x/y/w", VfsNodeType::Dir).unwrap();
+    assert_eq!(
+        root.create_all("x/y/z", VfsNodeType::File).err(),
+        Some(VfsError::AlreadyExists)
+    );
+    assert_eq!(
+        root.create_all("x/y/z", VfsNodeType::Dir).err(),
+        Some(VfsError::NotADirectory)
+    );
+    assert_eq!(
+        root.create_all("x/y/z/q", VfsNodeType::File).err(),
+        Some(VfsError::NotADirectory)
+    );
+    assert_eq!(
+        root.clone()
+            .lookup("x/y")
+            .ok()
+            .unwrap()
+            .get_attr()
+            .unwrap()
+            .nlink(),
+        3
+    );
+}
+
+#[test]
+fn test_inode_numbers() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("a", VfsNodeType::File).unwrap();
+    root.create("b", VfsNodeType::File).unwrap();
+    root.create("d", VfsNodeType::Dir).unwrap();
+    root.symlink("a", "l").unwrap();
+    let ino = |path: &str| {
+        root.clone()
+            .lookup(path)
+            .ok()
+            .unwrap()
+            .get_attr()
+            .unwrap()
+            .ino()
+    };
+    let a = root.clone().lookup("a").ok().unwrap();
+    root.link_node("hard", &a).unwrap();
+
+    let inos = [
+        ino("a"),
+        ino("b"),
+        ino("d"),
+        ino("l"),
+        root.get_attr().unwrap().ino(),
+    ];
+    assert!(inos.iter().all(|&ino| ino != 0));
+    let mut unique = inos.to_vec();
+    unique.sort_unstable();
+    unique.dedup();
+    assert_eq!(unique.len(), inos.len());
+    assert_eq!(ino("hard"), ino("a"));
+    // moving a node keeps its inode number
+    VfsNodeOps::rename(&*root, "b", "d/b").unwrap();
+    assert_eq!(ino("d/b"), inos[1]);
+}
+
+#[test]
+fn test_get_entries_with_type() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("d", VfsNodeType::Dir).unwrap();
+    root.create("f", VfsNodeType::File).unwrap();
+    root.symlink("f", "l").unwrap();
+    let flaky = Arc::new(FlakyNode {
+        broken: false.into(),
+    });
+    root.add_node("x", flaky.clone()).unwrap();
+    flaky
+        .broken
+        .store(true, std::sync::atomic::Ordering::Relaxed);
+    assert_eq!(
+        root.get_entries_with_type(),
+        [
+            ("d".into(), VfsNodeType::Dir),
+            ("f".into(), VfsNodeType::File),
+            ("l".into(), VfsNodeType::SymLink),
+        ]
+    );
+}
+
+#[test]
+fn test_clone_cow() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("big", VfsNodeType::File).unwrap();
+    let node = root.clone().lookup("big").ok().unwrap();
+    node.write_at(0, &[7; 4096]).unwrap();
+    let big = node.as_any().downcast_ref::<FileNode>().unwrap();
+
+    let copy: VfsNodeRef = big.clone_cow().unwrap();
+    assert_eq!(copy.get_attr().unwrap().nlink(), 0);
+    root.link_node("copy", &copy).unwrap();
+    assert_eq!(copy.get_attr().unwrap().nlink(), 1);
+    let (mut a, mut b) = ([0; 4096], [0; 4096]);
+    node.read_at(0, &mut a).unwrap();
+    copy.read_at(0, &mut b).unwrap();
+    assert_eq!(a, b);
+
+    copy.write_at(0, b"diverged").unwrap();
+    node.read_at(0, &mut a).unwrap();
+    assert_eq!(a, [7; 4096]);
+    copy.read_at(0, &mut b).unwrap();
+    assert_eq!(&b[..8], b"diverged");
+
+    let copy2 = big.clone_cow().unwrap();
+    node.truncate(1).unwrap();
+    assert_eq!(copy2.get_attr().unwrap().size(), 4096);
+    assert_eq!(ramfs.memory_usage(), 1 + 4096 * 2);
+}
+
+#[test]
+fn test_xattr() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("f", VfsNodeType::File).unwrap();
+    root.create("d", VfsNodeType::Dir).unwrap();
+    root.symlink("f", "l").unwrap();
+    for name in ["f", "d"] {
+        let node = root.clone().lookup(name).ok().unwrap();
+        node.set_xattr("user.mime_type", b"text/plain").unwrap();
+        node.set_xattr("user.origin", b"").unwrap();
+        assert_eq!(
+            node.list_xattr().unwrap(),
+            ["user.mime_type", "user.origin"]
+        );
+
+        assert_eq!(node.get_xattr("user.mime_type", &mut []).unwrap(), 10);
+        let mut buf = [0; 4];
+        assert_eq!(
+            node.get_xattr("user.mime_type", &mut buf).err(),
+            Some(VfsError::OutOfRange)
+        );
+        let mut buf = [0; 16];
+        assert_eq!(node.get_xattr("user.mime_type", &mut buf).unwrap(), 10);
+        assert_eq!(&buf[..10], b"text/plain");
+        assert_eq!(
+            node.get_xattr("user.missing", &mut buf).err(),
+            Some(VfsError::NotFound)
+        );
+
+        node.remove_xattr("user.origin").unwrap();
+        assert_eq!(
+            node.remove_xattr("user.origin").err(),
+            Some(VfsError::NotFound)
+        );
+        assert_eq!(node.list_xattr().unwrap(), ["user.mime_type"]);
+    }
+    let link = root.clone().lookup("l").ok().unwrap();
+    assert_eq!(
+        link.set_xattr("user.a", b"x").err(),
+        Some(VfsError::Unsupported)
+    );
+
+    root.set_subtree_readonly(true);
+    let file = root.clone().lookup("f").ok().unwrap();
+    assert!(file.set_xattr("user.a", b"x").is_err());
+    assert_eq!(file.get_xattr("user.mime_type", &mut []).unwrap(), 10);
+}
+
+#[test]
+fn test_readonly_mount() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("d", VfsNodeType::Dir).unwrap();
+    root.create("d/f", VfsNodeType::File).unwrap();
+    let file = root.clone().lookup("d/f").ok().unwrap();
+    file.write_at(0, b"baked").unwrap();
+    root.remove("d/f").unwrap();
+    root.link_node("f", &file).unwrap();
+
+    ramfs.set_readonly(true);
+    assert!(ramfs.is_readonly());
+    let err = Some(VfsError::ReadOnlyFilesystem);
+    assert_eq!(file.write_at(0, b"x").err(), err);
+    assert_eq!(file.truncate(0).err(), err);
+    assert_eq!(file.set_attr(&file.get_attr().unwrap()).err(), err);
+    assert_eq!(root.create("g", VfsNodeType::File).err(), err);
+    assert_eq!(root.remove("f").err(), err);
+    assert_eq!(root.symlink("f", "l").err(), err);
+    assert_eq!(VfsNodeOps::rename(&*root, "f", "g").err(), err);
+
+    let mut buf = [0; 8];
+    assert_eq!(file.read_at(0, &mut buf).unwrap(), 5);
+    assert_eq!(ramfs.read_to_string("/f").unwrap(), "baked");
+    let mut dirents: Vec<_> = (0..4).map(|_| VfsDirEntry::default()).collect();
+    assert_eq!(root.read_dir(0, &mut dirents).unwrap(), 4);
+
+    ramfs.set_readonly(false);
+    file.write_at(0, b"B").unwrap();
+}
+
+#[test]
+fn test_resolve_symlink() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_all("dir/sub", VfsNodeType::Dir).unwrap();
+    root.create("target", VfsNodeType::File).unwrap();
+    root.symlink("../target", "dir/link").unwrap();
+    root.symlink("./../../dir/./link", "dir/sub/chain").unwrap();
+    root.symlink("/dir/sub/loop", "dir/sub/loop").unwrap();
+
+    let target = root.clone().lookup("target").ok().unwrap();
+    let node = ramfs.resolve_symlink("/dir/link").ok().unwrap();
+    assert!(Arc::ptr_eq(&node, &target));
+    let node = ramfs.resolve_symlink("dir/sub/chain").ok().unwrap();
+    assert!(Arc::ptr_eq(&node, &target));
+    assert_eq!(
+        ramfs.resolve_symlink("/dir/sub/loop").err(),
+        Some(VfsError::FilesystemLoop)
+    );
+    assert_eq!(
+        ramfs.resolve_symlink("/target").err(),
+        Some(VfsError::InvalidInput)
+    );
+    assert_eq!(
+        ramfs.resolve_symlink("/missing").err(),
+        Some(VfsError::NotFound)
+    );
+}
+
+#[test]
+fn test_create_file_with() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let data: Vec<u8> = (0..100).collect();
+    let file = root.create_file_with("seed", &data).unwrap();
+    let mut buf = [0; 128];
+    assert_eq!(file.read_at(0, &mut buf).unwrap(), 100);
+    assert_eq!(&buf[..100], &data[..]);
+    let node = root.clone().lookup("seed").ok().unwrap();
+    assert!(Arc::ptr_eq(&node, &(file as VfsNodeRef)));
+    assert_eq!(ramfs.memory_usage(), 100);
+    assert_eq!(
+        root.create_file_with("seed", b"again").err(),
+        Some(VfsError::AlreadyExists)
+    );
+}
+
+#[test]
+fn test_swap() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let old = root.create_file_with("config", b"old").unwrap();
+    let new = root.create_file_with("config.new", b"new").unwrap();
+    let (old, new): (VfsNodeRef, VfsNodeRef) = (old, new);
+    assert_eq!(root.swap("config", "missing"), Err(VfsError::NotFound));
+    assert_eq!(root.swap("missing", "config"), Err(VfsError::NotFound));
+
+    let done = AtomicBool::new(false);
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            while !done.load(Ordering::Acquire) {
+                let node = root.clone().lookup("config").ok().unwrap();
+                assert!(Arc::ptr_eq(&node, &old) || Arc::ptr_eq(&node, &new));
+            }
+        });
+        for _ in 0..1000 {
+            root.swap("config", "config.new").unwrap();
+        }
+        done.store(true, Ordering::Release);
+    });
+    assert!(Arc::ptr_eq(
+        &root.clone().lookup("config").ok().unwrap(),
+        &old
+    ));
+
+    root.swap("config", "config.new").unwrap();
+    assert!(Arc::ptr_eq(
+        &root.clone().lookup("config").ok().unwrap(),
+        &new
+    ));
+    assert!(Arc::ptr_eq(
+        &root.clone().lookup("config.new").ok().unwrap(),
+        &old
+    ));
+    assert_eq!(root.len(), 2);
+    assert_eq!(new.get_attr().unwrap().nlink(), 1);
+}
+
+#[test]
+fn test_remove_prefix() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_files(&["a1", "a2", "b1"]).unwrap();
+    assert_eq!(root.remove_prefix("a").unwrap(), 2);
+    assert_eq!(root.get_entries(), ["b1"]);
+    assert_eq!(root.remove_prefix("a").unwrap(), 0);
+
+    root.create_node("sess_empty", VfsNodeType::Dir).unwrap();
+    root.create_all("sess_full/x", VfsNodeType::File).unwrap();
+    root.create_node("sess_1", VfsNodeType::File).unwrap();
+    assert_eq!(root.remove_prefix("sess_").unwrap(), 2);
+    assert_eq!(root.get_entries(), ["b1", "sess_full"]);
+    assert_eq!(root.remove_prefix("").unwrap(), 1);
+    assert_eq!(root.get_entries(), ["sess_full"]);
+}
+
+#[test]
+fn test_remove_prefix_case_insensitive() {
+    let ramfs = RamFileSystem::new_case_insensitive();
+    let root = ramfs.root_dir_node();
+    root.create_files(&["Sess_1", "SESS_2", "other"]).unwrap();
+    assert_eq!(root.remove_prefix("sess_").unwrap(), 2);
+    assert_eq!(root.get_entries(), ["other"]);
+    assert!(root.clone().lookup("sess_1").is_err());
+    root.create_node("sess_3", VfsNodeType::File).unwrap();
+}
+
+#[test]
+fn test_fallible_dynamic_symlink() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let available = Arc::new(AtomicBool::new(false));
+    let state = available.clone();
+    root.create_dynamic_symlink_fallible("cur", move || match state.load(Ordering::Relaxed) {
+        true => Ok("/proc/1".into()),
+        false => Err(VfsError::NotFound),
+    })
+    .unwrap();
+    let mut buf = [0; 16];
+    assert_eq!(
+        root.readlink("cur", &mut buf).err(),
+        Some(VfsError::NotFound)
+    );
+    let node = root.clone().lookup("cur").ok().unwrap();
+    assert_eq!(node.get_attr().err(), Some(VfsError::NotFound));
+    let link = node.as_any().downcast_ref::<SymlinkNode>().unwrap();
+    assert_eq!(link.target().err(), Some(VfsError::NotFound));
+    let mut dirents: Vec<_> = (0..4).map(|_| VfsDirEntry::default()).collect();
+    assert_eq!(root.read_dir(0, &mut dirents).unwrap(), 3);
+    assert_eq!(dirents[2].entry_type(), VfsNodeType::SymLink);
+
+    available.store(true, Ordering::Relaxed);
+    assert_eq!(root.readlink("cur", &mut buf).unwrap(), 7);
+    assert_eq!(&buf[..7], b"/proc/1");
+    assert_eq!(node.get_attr().unwrap().size(), 7);
+}
+
+#[test]
+fn test_symlink_target_validation() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    assert_eq!(root.symlink("", "empty"), Err(VfsError::InvalidInput));
+    assert_eq!(root.symlink("a\0b", "nul"), Err(VfsError::InvalidInput));
+    let long = "x".repeat(4097);
+    assert_eq!(root.symlink(&long, "long"), Err(VfsError::NameTooLong));
+    root.symlink(&long[..4096], "max").unwrap();
+    assert_eq!(root.get_entries(), ["max"]);
+
+    ramfs.set_max_symlink_len(8);
+    assert_eq!(root.symlink("/too/long", "l"), Err(VfsError::NameTooLong));
+    root.symlink("/ok", "l").unwrap();
+    root.create_dynamic_symlink("dyn", || "/generated".into())
+        .unwrap();
+    let mut buf = [0; 16];
+    assert_eq!(
+        root.readlink("dyn", &mut buf).err(),
+        Some(VfsError::NameTooLong)
+    );
+    root.create_dynamic_symlink("nul", || "\0".into()).unwrap();
+    assert_eq!(
+        root.readlink("nul", &mut buf).err(),
+        Some(VfsError::InvalidInput)
+    );
+}
+
+#[test]
+fn test_copy_range() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let data: Vec<u8> = (0..100).collect();
+    let src = root.create_file_with("src", &data).unwrap();
+    let dst = root.create_file_with("dst", b"head").unwrap();
+
+    assert_eq!(src.copy_range(&dst, 25, 8, 50).unwrap(), 50);
+    let mut buf = [0xff; 64];
+    assert_eq!(dst.read_at(0, &mut buf).unwrap(), 58);
+    assert_eq!(&buf[..8], b"head\0\0\0\0");
+    assert_eq!(&buf[8..58], &data[25..75]);
+    assert_eq!(ramfs.memory_usage(), 158);
+
+    // clamped at the end of the source
+    assert_eq!(src.copy_range(&dst, 90, 0, 50).unwrap(), 10);
+    assert_eq!(src.copy_range(&dst, 200, 0, 50).unwrap(), 0);
+    assert_eq!(dst.get_attr().unwrap().size(), 58);
+
+    // overlapping ranges of the same file
+    assert_eq!(src.copy_range(&src, 0, 10, 50).unwrap(), 50);
+    let mut buf = [0; 100];
+    src.read_at(0, &mut buf).unwrap();
+    assert_eq!(&buf[..10], &data[..10]);
+    assert_eq!(&buf[10..60], &data[..50]);
+    assert_eq!(&buf[60..], &data[60..]);
+}
+
+#[test]
+fn test_with_bytes() {
+    let ramfs = RamFileSystem::new();
+    let data: Vec<u8> = (0..10000).map(|i| (i * 7) as u8).collect();
+    let file = ramfs.root_dir_node().create_file_with("f", &data).unwrap();
+    let sum = file
+        .with_bytes(|bytes| bytes.iter().map(|&b| b as u64).sum::<u64>())
+        .unwrap();
+    let mut buf = vec![0; 10000];
+    assert_eq!(file.read_at(0, &mut buf).unwrap(), 10000);
+    assert_eq!(sum, buf.iter().map(|&b| b as u64).sum::<u64>());
+    assert_eq!(file.with_bytes(<[u8]>::len), Ok(10000));
+}
+
+#[test]
+fn test_reserve() {
+    const SIZE: usize = 1 << 20;
+    let ramfs = RamFileSystem::new();
+    let file = ramfs.root_dir_node().create_file_with("f", &[]).unwrap();
+    file.reserve(SIZE).unwrap();
+    assert_eq!(file.get_attr().unwrap().size(), 0);
+    assert_eq!(ramfs.memory_usage(), 0);
+
+    let chunk: Vec<u8> = (0..4096).map(|i| i as u8).collect();
+    for i in 0..SIZE / chunk.len() {
+        file.write_at((i * chunk.len()) as u64, &chunk).unwrap();
+    }
+    assert_eq!(file.get_attr().unwrap().size(), SIZE as u64);
+    file.with_bytes(|bytes| assert!(bytes.chunks(4096).all(|c| c == chunk)))
+        .unwrap();
+    assert_eq!(file.reserve(usize::MAX), Err(VfsError::StorageFull));
+}
+
+#[test]
+fn test_fifo() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("pipe", VfsNodeType::Fifo).unwrap();
+    let fifo = root.clone().lookup("pipe").ok().unwrap();
+    assert_eq!(fifo.get_attr().unwrap().file_type(), VfsNodeType::Fifo);
+
+    let mut buf = [0; 8];
+    assert_eq!(fifo.read_at(0, &mut buf).err(), Some(VfsError::WouldBlock));
+    assert_eq!(fifo.write_at(0, b"hello ").unwrap(), 6);
+    assert_eq!(fifo.write_at(100, b"world").unwrap(), 5);
+    assert_eq!(fifo.read_at(0, &mut buf).unwrap(), 8);
+    assert_eq!(&buf, b"hello wo");
+    assert_eq!(fifo.read_at(0, &mut buf).unwrap(), 3);
+    assert_eq!(&buf[..3], b"rld");
+
+    let big = vec![1; 70000];
+    assert_eq!(fifo.write_at(0, &big).unwrap(), 65536);
+    assert_eq!(fifo.write_at(0, b"x").err(), Some(VfsError::WouldBlock));
+    let fifo = fifo.as_any().downcast_ref::<FifoNode>().unwrap();
+    assert_eq!(fifo.available(), 65536);
+}
+
+#[test]
+fn test_fifo_set_attr() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_all("d/pipe", VfsNodeType::Fifo).unwrap();
+    let fifo = root.clone().lookup("d/pipe").ok().unwrap();
+    let mut attr = fifo.get_attr().unwrap();
+
+    ramfs.set_noexec(true);
+    attr.set_perm(VfsNodePerm::from_bits_truncate(0o755));
+    fifo.set_attr(&attr).unwrap();
+    assert_eq!(fifo.get_attr().unwrap().perm().bits(), 0o644);
+
+    let d = downcast_node::<DirNode>(root.clone().lookup("d").ok().unwrap()).unwrap();
+    d.set_subtree_readonly(true);
+    assert_eq!(fifo.set_attr(&attr), Err(VfsError::ReadOnlyFilesystem));
+    // moving the FIFO out of the read-only subtree makes it writable again
+    d.set_subtree_readonly(false);
+    VfsNodeOps::rename(&*root, "d/pipe", "pipe").unwrap();
+    d.set_subtree_readonly(true);
+    fifo.set_attr(&attr).unwrap();
+}
+
+#[test]
+fn test_reciprocal_moves() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_node("d1", VfsNodeType::Dir).unwrap();
+    root.create_node("d2", VfsNodeType::Dir).unwrap();
+    root.create_all("d1/x", VfsNodeType::File).unwrap();
+    root.create_all("d2/y", VfsNodeType::Dir).unwrap();
+    let d1 = downcast_node::<DirNode>(root.clone().lookup("d1").ok().unwrap()).unwrap();
+    let d2 = downcast_node::<DirNode>(root.clone().lookup("d2").ok().unwrap()).unwrap();
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            for _ in 0..5000 {
+                d1.rename("x", &d2, "x").unwrap();
+                d2.rename("x", &d1, "x").unwrap();
+            }
+        });
+        s.spawn(|| {
+            for _ in 0..5000 {
+                d2.rename("y", &d1, "y").unwrap();
+                d1.rename("y", &d2, "y").unwrap();
+            }
+        });
+    });
+    assert_eq!(d1.get_entries(), ["x"]);
+    assert_eq!(d2.get_entries(), ["y"]);
+    let y = root.clone().lookup("d2/y").ok().unwrap();
+    assert!(Arc::ptr_eq(&y.parent().unwrap(), &(d2 as VfsNodeRef)));
+}
+
+#[test]
+fn test_moves_with_nested_locks() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let names = ["c0", "c1", "c2", "c3"];
+    for name in names {
+        root.create_all(&format!("{name}/keep"), VfsNodeType::File)
+            .unwrap();
+        root.create_all(&format!("{name}/x{name}"), VfsNodeType::Dir)
+            .unwrap();
+    }
+    // created after its subdirectories, so they likely have lower addresses
+    root.create_node("p", VfsNodeType::Dir).unwrap();
+    let p = downcast_node::<DirNode>(root.clone().lookup("p").ok().unwrap()).unwrap();
+    for name in names {
+        root.rename(name, &p, name).unwrap();
+    }
+    std::thread::scope(|s| {
+        for name in names {
+            let p = &p;
+            s.spawn(move || {
+                let c = downcast_node::<DirNode>(p.clone().lookup(name).ok().unwrap()).unwrap();
+                let x = format!("x{name}");
+                for _ in 0..2000 {
+                    c.rename(&x, p, &x).unwrap();
+                    p.rename(&x, &c, &x).unwrap();
+                }
+            });
+        }
+        s.spawn(|| {
+            let mut dirents: Vec<_> = (0..16).map(|_| VfsDirEntry::default()).collect();
+            for _ in 0..4000 {
+                p.read_dir(0, &mut dirents).unwrap();
+            }
+        });
+        s.spawn(|| {
+            for _ in 0..4000 {
+                for name in names {
+                    assert_eq!(p.remove_node(name), Err(VfsError::DirectoryNotEmpty));
+                }
+            }
+        });
+    });
+    for name in names {
+        let c = root.clone().lookup(&format!("p/{name}")).ok().unwrap();
+        assert_eq!(c.get_attr().unwrap().nlink(), 3);
+    }
+}
+
+#[test]
+fn test_max_dir_entries() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    ramfs.set_max_dir_entries(3);
+    root.create_files(&["a", "b", "c"]).unwrap();
+    assert_eq!(
+        root.create_node("d", VfsNodeType::File),
+        Err(VfsError::StorageFull)
+    );
+    assert_eq!(root.symlink("a", "l"), Err(VfsError::StorageFull));
+    root.remove_node("c").unwrap();
+    root.create_node("d", VfsNodeType::Dir).unwrap();
+    VfsNodeOps::rename(&*root, "a", "c").unwrap();
+    assert_eq!(root.get_entries(), ["b", "c", "d"]);
+
+    // the limit of a directory overrides the one of the filesystem
+    let d = downcast_node::<DirNode>(root.clone().lookup("d").ok().unwrap()).unwrap();
+    d.set_max_entries(Some(1));
+    assert_eq!(root.rename("b", &d, "c"), Ok(()));
+    assert_eq!(root.rename("c", &d, "x"), Err(VfsError::StorageFull));
+    root.set_max_entries(Some(10));
+    root.create_files(&["1", "2", "3"]).unwrap();
+    assert_eq!(root.len(), 5);
+}
+
+#[test]
+fn test_watcher() {
+    use std::sync::Mutex;
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_node("sub", VfsNodeType::Dir).unwrap();
+    let sub = downcast_node::<DirNode>(root.clone().lookup("sub").ok().unwrap()).unwrap();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let seen = events.clone();
+    let dir = Arc::downgrade(&root);
+    root.add_watcher(move |event| {
+        // the directory is unlocked while watchers run
+        assert!(dir.upgrade().unwrap().len() < 10);
+        seen.lock().unwrap().push(event.clone());
+    });
+
+    root.create("a", VfsNodeType::File).unwrap();
+    VfsNodeOps::rename(&*root, "a", "b").unwrap();
+    root.rename("b", &sub, "c").unwrap();
+    sub.rename("c", &root, "d").unwrap();
+    root.remove("d").unwrap();
+    sub.create("x", VfsNodeType::File).unwrap();
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            FsEvent::Create("a".into()),
+            FsEvent::Rename {
+                from: "a".into(),
+                to: "b".into(),
+            },
+            FsEvent::Remove("b".into()),
+            FsEvent::Create("d".into()),
+            FsEvent::Remove("d".into()),
+        ]
+    );
+
+    root.clear_watchers();
+    root.create("e", VfsNodeType::File).unwrap();
+    assert_eq!(events.lock().unwrap().len(), 5);
+}
+
+#[test]
+fn test_watch_swap() {
+    use std::sync::Mutex;
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_files(&["a", "b"]).unwrap();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let seen = events.clone();
+    root.add_watcher(move |event| seen.lock().unwrap().push(event.clone()));
+
+    root.swap("a", "b").unwrap();
+    root.swap("a", "a").unwrap();
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            FsEvent::Rename {
+                from: "a".into(),
+                to: "b".into(),
+            },
+            FsEvent::Rename {
+                from: "b".into(),
+                to: "a".into(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_read_dir_after() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_files(&["b", "c", "d", "e"]).unwrap();
+
+    let mut dirents: Vec<_> = (0..2).map(|_| VfsDirEntry::default()).collect();
+    let mut names = Vec::new();
+    let mut after = None;
+    loop {
+        let n = root.read_dir_after(after.as_deref(), &mut dirents).unwrap();
+        if n == 0 {
+            break;
+        }
+        for ent in &dirents[..n] {
+            names.push(String::from_utf8(ent.name_as_bytes().to_vec()).unwrap());
+        }
+        after = names.last().cloned();
+        if names.len() == 2 {
+            root.create("a", VfsNodeType::File).unwrap(); // sorts first
+            root.remove("b").unwrap();
+        }
+    }
+    assert_eq!(names, ["b", "c", "d", "e"]);
+    assert_eq!(root.read_dir_after(Some("0"), &mut dirents).unwrap(), 2);
+    assert_eq!(dirents[0].name_as_bytes(), b"a");
+}
+
+#[test]
+fn test_from_manifest() {
+    let ramfs = RamFileSystem::from_manifest(&[
+        ("/etc/hosts", FsEntry::File(b"127.0.0.1 localhost\n")),
+        ("/etc", FsEntry::Dir),
+        ("/usr/lib/libc.so", FsEntry::Symlink("libc.so.6")),
+        ("/usr/lib/libc.so.6", FsEntry::File(b"\x7fELF")),
+        ("/tmp", FsEntry::Dir),
+    ])
+    .unwrap();
+    assert_eq!(
+        ramfs.read_to_string("/etc/hosts").unwrap(),
+        "127.0.0.1 localhost\n"
+    );
+    let root = ramfs.root_dir_node();
+    assert!(root
+        .clone()
+        .lookup("etc")
+        .ok()
+        .unwrap()
+        .get_attr()
+        .unwrap()
+        .is_dir());
+    assert!(root
+        .clone()
+        .lookup("tmp")
+        .ok()
+        .unwrap()
+        .get_attr()
+        .unwrap()
+        .is_dir());
+    let lib = ramfs.resolve_symlink("/usr/lib/libc.so").unwrap();
+    assert_eq!(lib.get_attr().unwrap().size(), 4);
+    assert_eq!(root.get_entries(), ["etc", "tmp", "usr"]);
+
+    assert_eq!(
+        RamFileSystem::from_manifest(&[("/a", FsEntry::Dir), ("a/", FsEntry::File(b""))]).err(),
+        Some(VfsError::AlreadyExists)
+    );
+}
+
+#[test]
+fn test_create_and_get() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_node("d", VfsNodeType::Dir).unwrap();
+    let file = root.create_and_get("d/f", VfsNodeType::File).unwrap();
+    assert_eq!(file.get_attr().unwrap().file_type(), VfsNodeType::File);
+    assert!(Arc::ptr_eq(
+        &file,
+        &root.clone().lookup("d/f").ok().unwrap()
+    ));
+    let dir = root.create_and_get("/d/sub", VfsNodeType::Dir).unwrap();
+    assert_eq!(dir.get_attr().unwrap().file_type(), VfsNodeType::Dir);
+    assert!(Arc::ptr_eq(
+        &dir.parent().unwrap(),
+        &root.clone().lookup("d").ok().unwrap()
+    ));
+
+    let node = root.create_and_get("d/sub/..", VfsNodeType::Dir).unwrap();
+    assert!(Arc::ptr_eq(&node, &root.clone().lookup("d").ok().unwrap()));
+    let node = root.create_and_get("d/.", VfsNodeType::Dir).unwrap();
+    assert!(Arc::ptr_eq(&node, &root.clone().lookup("d").ok().unwrap()));
+    assert_eq!(
+        root.create_and_get("d/f", VfsNodeType::File).err(),
+        Some(VfsError::AlreadyExists)
+    );
+    assert_eq!(
+        root.create_and_get("missing/f", VfsNodeType::File).err(),
+        Some(VfsError::NotFound)
+    );
+}
+
+#[test]
+fn test_create_and_get_race() {
+    use std::sync::Barrier;
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let barrier = Barrier::new(4);
+    for i in 0..500 {
+        let name = format!("d{i}");
+        let created: Vec<_> = std::thread::scope(|s| {
+            let threads: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        barrier.wait();
+                        root.create_and_get(&name, VfsNodeType::Dir)
+                    })
+                })
+                .collect();
+            threads.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+        // exactly one creator wins, and gets the node that ends up in the tree
+        let mut winners = created.into_iter().filter_map(Result::ok);
+        let node = winners.next().unwrap();
+        assert!(winners.next().is_none());
+        assert!(Arc::ptr_eq(
+            &node,
+            &root.clone().lookup(&name).ok().unwrap()
+        ));
+    }
+}
+
+#[test]
+fn test_subtree_bytes() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_file_with("outside", &[0; 50]).unwrap();
+    root.create_all("sub/deep", VfsNodeType::Dir).unwrap();
+    let sub = downcast_node::<DirNode>(root.clone().lookup("sub").ok().unwrap()).unwrap();
+    let a = sub.create_file_with("a", &[1; 100]).unwrap();
+    let deep = downcast_node::<DirNode>(sub.clone().lookup("deep").ok().unwrap()).unwrap();
+    deep.create_file_with("b", &[2; 200]).unwrap();
+    deep.link_node("a", &(a.clone() as VfsNodeRef)).unwrap();
+    assert_eq!(sub.subtree_bytes(), 300);
+    assert_eq!(root.subtree_bytes(), 350);
+
+    assert_eq!(a.byte_len(), 100);
+    assert!(a.capacity() >= 100);
+    a.reserve(1000).unwrap();
+    assert_eq!(a.byte_len(), 100);
+    assert!(a.capacity() >= 1100);
+}
+
+#[test]
+fn test_chown() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let file = root.create_file_with("f", b"").unwrap();
+    let attr = file.get_attr().unwrap();
+    assert_eq!((attr.uid(), attr.gid()), (0, 0));
+
+    file.chown(Some(1000), Some(1000)).unwrap();
+    let attr = file.get_attr().unwrap();
+    assert_eq!((attr.uid(), attr.gid()), (1000, 1000));
+    file.chown(None, Some(50)).unwrap();
+    let attr = file.get_attr().unwrap();
+    assert_eq!((attr.uid(), attr.gid()), (1000, 50));
+
+    // set_attr applies the owner too, so copies keep it
+    let copy = ramfs.snapshot().unwrap();
+    let attr = copy
+        .root_dir_node()
+        .lookup("f")
+        .ok()
+        .unwrap()
+        .get_attr()
+        .unwrap();
+    assert_eq!((attr.uid(), attr.gid()), (1000, 50));
+
+    root.chown(Some(1), None).unwrap();
+    assert_eq!(root.get_attr().unwrap().uid(), 1);
+    ramfs.set_readonly(true);
+    assert_eq!(file.chown(Some(0), None), Err(VfsError::ReadOnlyFilesystem));
+}
+
+#[test]
+fn test_add_node_rejects_ancestor() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let b = root.create_dir_all("a/b").unwrap();
+    assert_eq!(
+        b.add_node("root", root.clone()),
+        Err(VfsError::InvalidInput)
+    );
+    assert_eq!(b.add_node("self", b.clone()), Err(VfsError::InvalidInput));
+    assert!(b.is_empty());
+    let c = root.create_dir_all("c").unwrap();
+    b.add_node("c", c).unwrap();
+    assert!(ramfs.detect_cycles().is_empty());
+}
+
+#[test]
+fn test_list_sorted() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_file_with("c", b"ccc").unwrap();
+    root.create_node("a", VfsNodeType::Dir).unwrap();
+    root.create_file_with("b", b"b").unwrap();
+    let flaky = Arc::new(FlakyNode {
+        broken: AtomicBool::new(false),
+    });
+    root.add_node("ab", flaky.clone()).unwrap();
+    flaky.broken.store(true, Ordering::Relaxed);
+
+    let list = root.list_sorted();
+    let names: Vec<_> = list.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["a", "b", "c"]);
+    assert!(list[0].1.is_dir());
+    assert_eq!(list[2].1.size(), 3);
+    flaky.broken.store(false, Ordering::Relaxed);
+    assert_eq!(root.list_sorted()[1].0, "ab");
+}
+
+#[test]
+fn test_write_at_capacity_boundary() {
+    let ramfs = RamFileSystem::with_capacity(100);
+    let file = ramfs
+        .root_dir_node()
+        .create_file_with("f", &[1; 60])
+        .unwrap();
+    assert_eq!(file.write_at(50, &[2; 50]).unwrap(), 50);
+    assert_eq!(file.get_attr().unwrap().size(), 100);
+    assert_eq!(ramfs.memory_usage(), 100);
+
+    // a write past the capacity changes nothing, not even the part that fits
+    assert_eq!(file.write_at(90, &[3; 20]), Err(VfsError::StorageFull));
+    assert_eq!(file.write_at(100, &[3]), Err(VfsError::StorageFull));
+    assert_eq!(file.get_attr().unwrap().size(), 100);
+    assert_eq!(ramfs.memory_usage(), 100);
+    file.with_bytes(|bytes| {
+        assert!(bytes[..50].iter().all(|&b| b == 1));
+        assert!(bytes[50..].iter().all(|&b| b == 2));
+    })
+    .unwrap();
+
+    // overwriting within the current size always fits
+    assert_eq!(file.write_at(90, &[4; 10]).unwrap(), 10);
+    assert_eq!(file.write_at(u64::MAX, &[4]), Err(VfsError::InvalidInput));
+}
+
+#[test]
+fn test_path_of() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_all("a/b/c", VfsNodeType::Dir).unwrap();
+    root.create_all("a/b/f", VfsNodeType::File).unwrap();
+    let c = root.clone().lookup("a/b/c").ok().unwrap();
+    let f = root.clone().lookup("a/b/f").ok().unwrap();
+    assert_eq!(ramfs.path_of(&c).unwrap(), "/a/b/c");
+    assert_eq!(ramfs.path_of(&f).unwrap(), "/a/b/f");
+    assert_eq!(ramfs.path_of(&(root.clone() as VfsNodeRef)).unwrap(), "/");
+
+    let b = downcast_node::<DirNode>(root.clone().lookup("a/b").ok().unwrap()).unwrap();
+    assert_eq!(b.name_of(&c).unwrap(), "c");
+    assert_eq!(root.name_of(&c), None);
+    VfsNodeOps::rename(&*root, "a/b/c", "c2").unwrap();
+    assert_eq!(ramfs.path_of(&c).unwrap(), "/c2");
+    root.remove("a/b/f").unwrap();
+    assert_eq!(ramfs.path_of(&f), None);
+    let other = RamFileSystem::new();
+    assert_eq!(other.path_of(&c), None);
+}
+
+#[test]
+fn test_link_paths() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_all("a/f", VfsNodeType::File).unwrap();
+    root.create_node("b", VfsNodeType::Dir).unwrap();
+    ramfs.link("/a/f", "/b/g").unwrap();
+    let f = root.clone().lookup("a/f").ok().unwrap();
+    let g = root.clone().lookup("b/g").ok().unwrap();
+    assert!(Arc::ptr_eq(&f, &g));
+    assert_eq!(f.get_attr().unwrap().nlink(), 2);
+    f.write_at(0, b"shared").unwrap();
+    let mut buf = [0; 6];
+    g.read_at(0, &mut buf).unwrap();
+    assert_eq!(&buf, b"shared");
+
+    let a = root.clone().lookup("a").ok().unwrap();
+    a.link("f", "h").unwrap();
+    assert_eq!(f.get_attr().unwrap().nlink(), 3);
+    assert_eq!(ramfs.link("/a/f", "/b/g"), Err(VfsError::AlreadyExists));
+    assert_eq!(ramfs.link("/a", "/b/a"), Err(VfsError::PermissionDenied));
+    assert_eq!(ramfs.link("/missing", "/b/m"), Err(VfsError::NotFound));
+
+    root.remove("a/f").unwrap();
+    root.remove("a/h").unwrap();
+    assert_eq!(g.get_attr().unwrap().nlink(), 1);
+    assert_eq!(ramfs.read_to_string("/b/g").unwrap(), "shared");
+}
@@ -0,0 +1,34 @@
+//! Conversion of [`VfsError`]s to POSIX error numbers.
+
+use axerrno::LinuxError;
+
+use crate::VfsError;
+
+/// Returns the conventional POSIX errno (e.g. `ENOENT` for
+/// [`VfsError::NotFound`]) corresponding to `e`, as a positive number.
+///
+/// The mapping is the one of [`LinuxError`], which covers every
+/// [`VfsError`] variant.
+pub fn vfs_error_to_errno(e: VfsError) -> i32 {
+    LinuxError::from(e).code()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vfs_error_to_errno() {
+        assert_eq!(vfs_error_to_errno(VfsError::NotFound), 2); // ENOENT
+        assert_eq!(vfs_error_to_errno(VfsError::PermissionDenied), 13); // EACCES
+        assert_eq!(vfs_error_to_errno(VfsError::AlreadyExists), 17); // EEXIST
+        assert_eq!(vfs_error_to_errno(VfsError::NotADirectory), 20); // ENOTDIR
+        assert_eq!(vfs_error_to_errno(VfsError::IsADirectory), 21); // EISDIR
+        assert_eq!(vfs_error_to_errno(VfsError::InvalidInput), 22); // EINVAL
+        assert_eq!(vfs_error_to_errno(VfsError::StorageFull), 28); // ENOSPC
+        assert_eq!(vfs_error_to_errno(VfsError::ReadOnlyFilesystem), 30); // EROFS
+        assert_eq!(vfs_error_to_errno(VfsError::NameTooLong), 36); // ENAMETOOLONG
+        assert_eq!(vfs_error_to_errno(VfsError::DirectoryNotEmpty), 39); // ENOTEMPTY
+        assert_eq!(vfs_error_to_errno(VfsError::FilesystemLoop), 40); // ELOOP
+    }
+}
@@ -22,6 +22,7 @@
 //! | [`open()`](VfsNodeOps::open) | Do something when the node is opened | both |
 //! | [`release()`](VfsNodeOps::release) | Do something when the node is closed | both |
 //! | [`get_attr()`](VfsNodeOps::get_attr) | Get the attributes of the node | both |
+//! | [`set_attr()`](VfsNodeOps::set_attr) | Set the attributes of the node | both |
 //! | [`read_at()`](VfsNodeOps::read_at) | Read data from the file | file |
 //! | [`write_at()`](VfsNodeOps::write_at) | Write data to the file | file |
 //! | [`fsync()`](VfsNodeOps::fsync) | Synchronize the file data to disk | file |
@@ -32,8 +33,13 @@
 //! | [`remove()`](VfsNodeOps::remove) | Remove the node with the given path | directory |
 //! | [`read_dir()`](VfsNodeOps::read_dir) | Read directory entries | directory |
 //! | [`symlink()`](VfsNodeOps::symlink) | Create a symbolic link | directory |
+//! | [`link()`](VfsNodeOps::link) | Create a hard link | directory |
 //! | [`readlink()`](VfsNodeOps::readlink) | Read symbolic link target | symlink |
 //! | [`is_symlink()`](VfsNodeOps::is_symlink) | Check if node is a symbolic link | both |
+//! | [`get_xattr()`](VfsNodeOps::get_xattr) | Get an extended attribute | both |
+//! | [`set_xattr()`](VfsNodeOps::set_xattr) | Set an extended attribute | both |
+//! | [`list_xattr()`](VfsNodeOps::list_xattr) | List the extended attributes | both |
+//! | [`remove_xattr()`](VfsNodeOps::remove_xattr) | Remove an extended attribute | both |
 //!
 //! [inodes]: https://en.wikipedia.org/wiki/Inode
 
@@ -44,14 +50,21 @@ extern crate alloc;
 mod macros;
 mod structs;
 
+#[cfg(feature = "errno")]
+mod errno;
 pub mod path;
 
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use axerrno::{ax_err, AxError, AxResult};
 use axio::PollState;
 
 pub use self::structs::{FileSystemInfo, VfsDirEntry, VfsNodeAttr, VfsNodePerm, VfsNodeType};
 
+#[cfg(feature = "errno")]
+pub use self::errno::vfs_error_to_errno;
+
 /// A wrapper of [`Arc<dyn VfsNodeOps>`].
 pub type VfsNodeRef = Arc<dyn VfsNodeOps>;
 
@@ -104,6 +117,14 @@ pub trait VfsNodeOps: Send + Sync {
         ax_err!(Unsupported)
     }
 
+    /// Set the attributes of the node.
+    ///
+    /// Only the permission bits and the owner of `attr` are applied; the
+    /// other fields are determined by the node itself and are ignored.
+    fn set_attr(&self, _attr: &VfsNodeAttr) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
     // file operations:
 
     /// Read data from the file at the given offset.
@@ -169,6 +190,11 @@ pub trait VfsNodeOps: Send + Sync {
         ax_err!(Unsupported)
     }
 
+    /// Create a hard link at `dst_path` to the existing node at `src_path`.
+    fn link(&self, _src_path: &str, _dst_path: &str) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
     /// Convert `&self` to [`&dyn Any`][1] that can use
     /// [`Any::downcast_ref`][2].
     ///
@@ -188,6 +214,31 @@ pub trait VfsNodeOps: Send + Sync {
         false
     }
 
+    /// Get the value of the extended attribute `name` into `buf`, and return
+    /// its length.
+    ///
+    /// If `buf` is empty, only the length is returned, so callers can size
+    /// the buffer. If `buf` is non-empty but too short,
+    /// [`OutOfRange`](AxError::OutOfRange) is returned.
+    fn get_xattr(&self, _name: &str, _buf: &mut [u8]) -> VfsResult<usize> {
+        ax_err!(Unsupported)
+    }
+
+    /// Set the extended attribute `name` to `value`, creating it if needed.
+    fn set_xattr(&self, _name: &str, _value: &[u8]) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
+    /// List the names of the extended attributes of the node.
+    fn list_xattr(&self) -> VfsResult<Vec<String>> {
+        ax_err!(Unsupported)
+    }
+
+    /// Remove the extended attribute `name`.
+    fn remove_xattr(&self, _name: &str) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
     fn add_node(&self, _name: &'static str, _ty: VfsNodeRef) -> VfsResult {
         ax_err!(Unsupported)
     }
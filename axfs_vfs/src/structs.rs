@@ -16,6 +16,22 @@ pub struct VfsNodeAttr {
     size: u64,
     /// Number of 512B blocks allocated.
     blocks: u64,
+    /// Number of hard links.
+    nlink: u64,
+    /// Inode number.
+    ino: u64,
+    /// User ID of the owner.
+    uid: u32,
+    /// Group ID of the owner.
+    gid: u32,
+    /// Time of last access, in nanoseconds.
+    atime: u64,
+    /// Time of last modification, in nanoseconds.
+    mtime: u64,
+    /// Time of last status change, in nanoseconds.
+    ctime: u64,
+    /// Time of creation, in nanoseconds.
+    btime: u64,
 }
 
 bitflags::bitflags! {
@@ -205,6 +221,14 @@ impl VfsNodeAttr {
             ty,
             size,
             blocks,
+            nlink: 1,
+            ino: 0,
+            uid: 0,
+            gid: 0,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+            btime: 0,
         }
     }
 
@@ -215,6 +239,14 @@ impl VfsNodeAttr {
             ty: VfsNodeType::File,
             size,
             blocks,
+            nlink: 1,
+            ino: 0,
+            uid: 0,
+            gid: 0,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+            btime: 0,
         }
     }
 
@@ -226,6 +258,14 @@ impl VfsNodeAttr {
             ty: VfsNodeType::Dir,
             size,
             blocks,
+            nlink: 1,
+            ino: 0,
+            uid: 0,
+            gid: 0,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+            btime: 0,
         }
     }
 
@@ -239,6 +279,89 @@ impl VfsNodeAttr {
         self.blocks
     }
 
+    /// Returns the number of hard links to the node.
+    pub const fn nlink(&self) -> u64 {
+        self.nlink
+    }
+
+    /// Sets the number of hard links to the node.
+    pub fn set_nlink(&mut self, nlink: u64) {
+        self.nlink = nlink
+    }
+
+    /// Returns the inode number of the node.
+    pub const fn ino(&self) -> u64 {
+        self.ino
+    }
+
+    /// Sets the inode number of the node.
+    pub fn set_ino(&mut self, ino: u64) {
+        self.ino = ino
+    }
+
+    /// Returns the user ID of the owner of the node.
+    pub const fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    /// Sets the user ID of the owner of the node.
+    pub fn set_uid(&mut self, uid: u32) {
+        self.uid = uid
+    }
+
+    /// Returns the group ID of the owner of the node.
+    pub const fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    /// Sets the group ID of the owner of the node.
+    pub fn set_gid(&mut self, gid: u32) {
+        self.gid = gid
+    }
+
+    /// Returns the time of last access to the node's content, in
+    /// nanoseconds.
+    pub const fn atime(&self) -> u64 {
+        self.atime
+    }
+
+    /// Sets the time of last access to the node's content.
+    pub fn set_atime(&mut self, atime: u64) {
+        self.atime = atime
+    }
+
+    /// Returns the time of last modification of the node's content, in
+    /// nanoseconds.
+    pub const fn mtime(&self) -> u64 {
+        self.mtime
+    }
+
+    /// Sets the time of last modification of the node's content.
+    pub fn set_mtime(&mut self, mtime: u64) {
+        self.mtime = mtime
+    }
+
+    /// Returns the time of last change of the node's content or metadata, in
+    /// nanoseconds.
+    pub const fn ctime(&self) -> u64 {
+        self.ctime
+    }
+
+    /// Sets the time of last change of the node's content or metadata.
+    pub fn set_ctime(&mut self, ctime: u64) {
+        self.ctime = ctime
+    }
+
+    /// Returns the creation (birth) time of the node, in nanoseconds.
+    pub const fn btime(&self) -> u64 {
+        self.btime
+    }
+
+    /// Sets the creation (birth) time of the node.
+    pub fn set_btime(&mut self, btime: u64) {
+        self.btime = btime
+    }
+
     /// Returns the permission of the node.
     pub const fn perm(&self) -> VfsNodePerm {
         self.mode